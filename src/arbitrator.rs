@@ -0,0 +1,337 @@
+use crate::clock::JoinHandle;
+use crate::{
+    philosopher_rng, Activity, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
+};
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// How long the waiter or a hungry philosopher waits for a message before
+/// checking whether the kill switch has been activated (or the philosopher
+/// has starved).
+const WAIT_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The arbitrator (or "waiter") solution. A single waiter thread owns all of
+/// the forks, and a philosopher has to ask the waiter for permission before
+/// eating. The waiter only grants both forks at once, so nobody ever holds one
+/// fork while waiting for the other. Requests that can't be granted straight
+/// away are queued. The forks the request at the front of the queue is
+/// waiting for are held back for it, so nobody can overtake it and every
+/// request is granted eventually, but a request further back that needs
+/// neither of them can be granted first.
+///
+/// A bit like `sequential`, except the waiter reacts to whoever is hungry
+/// instead of going round the table telling people to eat.
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let n_philosophers = config.n_philosophers;
+    let (request_tx, request_rx) = mpsc::channel::<Request>();
+
+    let mut philosophers = vec![];
+    let mut grant_txs: Vec<Sender<Grant>> = vec![];
+    for i in 1..n_philosophers + 1 {
+        let (grant_tx, grant_rx) = mpsc::channel();
+        let philosopher = Philosopher::new(
+            i,
+            request_tx.clone(),
+            grant_rx,
+            clock.clone(),
+            sink.clone(),
+            kill_switch.clone(),
+            config.clone(),
+        );
+        philosophers.push(philosopher);
+        grant_txs.push(grant_tx);
+    }
+
+    let mut handles: Vec<JoinHandle<()>> = vec![];
+
+    let mut waiter =
+        Waiter::new(n_philosophers, request_rx, grant_txs, clock.clone());
+    let waiter_kill_switch = kill_switch.clone();
+    handles.push(clock.spawn(move || {
+        waiter.run(&waiter_kill_switch);
+    }));
+
+    for mut philosopher in philosophers {
+        let handle = clock.spawn(move || {
+            philosopher.run();
+        });
+        handles.push(handle);
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+/// See [`main`].
+pub struct Arbitrator;
+
+impl Strategy for Arbitrator {
+    fn name(&self) -> &'static str {
+        "arbitrator"
+    }
+
+    fn description(&self) -> &'static str {
+        "A waiter thread hands out both forks at once"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: true,
+            centralised: true,
+        }
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
+
+/// Messages from a philosopher to the waiter.
+enum Request {
+    /// Ask for both forks. The waiter replies with a `Grant` once the
+    /// philosopher is allowed to eat.
+    Forks(usize),
+    /// Put both forks back.
+    Release(usize),
+}
+
+/// The waiter's reply to `Request::Forks`, handing over these forks.
+struct Grant {
+    forks: [usize; 2],
+}
+
+struct Waiter {
+    /// `true` if the fork is on the table, `false` if a philosopher has it.
+    forks: Vec<bool>,
+    queue: VecDeque<usize>,
+    request_rx: Receiver<Request>,
+    grant_txs: Vec<Sender<Grant>>,
+    clock: Clock,
+}
+
+impl Waiter {
+    pub fn new(
+        n_philosophers: usize,
+        request_rx: Receiver<Request>,
+        grant_txs: Vec<Sender<Grant>>,
+        clock: Clock,
+    ) -> Self {
+        Self {
+            forks: vec![true; n_philosophers],
+            queue: VecDeque::new(),
+            request_rx,
+            grant_txs,
+            clock,
+        }
+    }
+
+    pub fn run(&mut self, kill_switch: &AtomicBool) {
+        while !kill_switch.load(Ordering::Relaxed) {
+            match self.clock.recv_timeout(&self.request_rx, WAIT_INTERVAL) {
+                Ok(Request::Forks(id)) => {
+                    self.queue.push_back(id);
+                    self.grant_queued();
+                }
+                Ok(Request::Release(id)) => {
+                    let (left, right) = self.fork_indices(id);
+                    self.forks[left] = true;
+                    self.forks[right] = true;
+                    self.grant_queued();
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Go through the queue in the order the requests arrived, granting any
+    /// that can now be granted. The first request that can't be granted
+    /// keeps its forks reserved, so someone behind it can't take one of them
+    /// first. Only the front of the queue reserves anything, otherwise every
+    /// blocked request would chain everyone behind it to the first one
+    /// waiting.
+    fn grant_queued(&mut self) {
+        let mut reserved: Option<(usize, usize)> = None;
+        let queued = std::mem::take(&mut self.queue);
+        for id in queued {
+            let (left, right) = self.fork_indices(id);
+            let available = |fork: usize| {
+                self.forks[fork]
+                    && !reserved.is_some_and(|(a, b)| fork == a || fork == b)
+            };
+            if available(left) && available(right) {
+                self.forks[left] = false;
+                self.forks[right] = false;
+                // The philosopher only stops listening once the kill switch
+                // is active, at which point it doesn't matter.
+                let _ = self.grant_txs[id - 1].send(Grant {
+                    forks: [left, right],
+                });
+            } else {
+                log::debug!("Waiter queued philosopher {id}");
+                reserved.get_or_insert((left, right));
+                self.queue.push_back(id);
+            }
+        }
+    }
+
+    fn fork_indices(&self, id: usize) -> (usize, usize) {
+        let n = self.forks.len();
+        ((id - 1) % n, id % n)
+    }
+}
+
+struct Philosopher {
+    id: usize,
+    state: PhilosopherState,
+    request_tx: Sender<Request>,
+    grant_rx: Receiver<Grant>,
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
+}
+
+impl Philosopher {
+    pub fn new(
+        id: usize,
+        request_tx: Sender<Request>,
+        grant_rx: Receiver<Grant>,
+        clock: Clock,
+        sink: EventSink,
+        kill_switch: Arc<AtomicBool>,
+        config: SimConfig,
+    ) -> Self {
+        Self {
+            id,
+            state: PhilosopherState::Thinking,
+            request_tx,
+            grant_rx,
+            clock,
+            sink,
+            kill_switch,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            config,
+        }
+    }
+
+    fn request(&self, request: Request) {
+        // The waiter only stops listening once the kill switch is active.
+        let _ = self.request_tx.send(request);
+    }
+}
+
+impl Diner for Philosopher {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
+    }
+
+    fn think(&mut self) {
+        log::debug!("Philosopher {} is thinking", self.id);
+        self.state = PhilosopherState::Thinking;
+        self.emit(EventKind::StartedThinking);
+        self.sleep(Activity::Thinking);
+
+        log::debug!("Philosopher {} is hungry", self.id);
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
+    }
+
+    fn eat(&mut self) {
+        self.request(Request::Forks(self.id));
+        while let PhilosopherState::Hungry(_) = self.state {
+            match self.clock.recv_timeout(&self.grant_rx, WAIT_INTERVAL) {
+                Ok(Grant { forks }) => {
+                    // The waiter has handed over both forks.
+                    for fork in forks {
+                        self.emit(EventKind::AcquiredFork(fork));
+                    }
+                    log::debug!("Philosopher {} is eating", self.id);
+                    self.state = PhilosopherState::Eating;
+                    self.emit(EventKind::StartedEating);
+                    self.sleep(Activity::Eating);
+                    self.emit(EventKind::FinishedEating);
+                    log::debug!("Philosopher {} is full", self.id);
+                    for fork in forks {
+                        self.emit(EventKind::ReleasedFork(fork));
+                    }
+                    self.request(Request::Release(self.id));
+                }
+                Err(_) if self.has_starved_to_death() => {
+                    self.state = PhilosopherState::Dead;
+                    self.emit(EventKind::Died);
+                }
+                Err(_) if self.is_kill_switch_active() => break,
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
+    fn current_state(&self) -> PhilosopherState {
+        self.state
+    }
+
+    fn is_kill_switch_active(&self) -> bool {
+        self.kill_switch.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nobody_overtakes_the_front_of_the_queue() {
+        let (_request_tx, request_rx) = mpsc::channel();
+        let (grant_txs, grant_rxs): (Vec<_>, Vec<_>) =
+            (0..5).map(|_| mpsc::channel()).unzip();
+        let mut waiter = Waiter::new(5, request_rx, grant_txs, Clock::real());
+        let granted = |id: usize| grant_rxs[id - 1].try_recv().is_ok();
+
+        waiter.queue.push_back(1);
+        waiter.grant_queued();
+        assert!(granted(1));
+        // 2 needs fork 1 from 1, and 3 needs fork 2, which is free but held
+        // back for 2. 4 needs neither, so it can go ahead.
+        waiter.queue.extend([2, 3, 4]);
+        waiter.grant_queued();
+        assert!(!granted(2) && !granted(3));
+        assert!(granted(4));
+        assert_eq!(waiter.queue, [2, 3]);
+    }
+}
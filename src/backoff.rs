@@ -0,0 +1,236 @@
+//! What philosophers do after failing to pick up their forks, for strategies
+//! that poll for them (like `two_forks` and `sequential`).
+//!
+//! Trying again straight away costs the most CPU time, and backing off for
+//! longer costs meals instead, so the policy is there to be compared. Each
+//! one is written the same way on the command line and in traces, with any
+//! durations in milliseconds:
+//!
+//! ```text
+//! none             try again straight away
+//! yield            let another thread run first
+//! fixed:1          sleep for 1ms
+//! exp:0.1,10       sleep for 0.1ms, doubling after every failure in a row up
+//!                  to 10ms, each time somewhere between half that and all of
+//!                  it
+//! random:0.1,2     sleep for anything from 0.1ms up to 2ms
+//! ```
+//!
+//! On a virtual clock `none` and `yield` are the same thing, since there's
+//! only ever one thread running anyway.
+
+use crate::distribution::{as_millis, millis};
+use crate::{philosopher_rng, Clock};
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackoffPolicy {
+    #[default]
+    None,
+    Yield,
+    Fixed(Duration),
+    /// `base` doubled for every failure in a row, up to `max`, with jitter.
+    Exponential {
+        base: Duration,
+        max: Duration,
+    },
+    /// From `min` up to but not including `max`.
+    Random {
+        min: Duration,
+        max: Duration,
+    },
+}
+
+impl BackoffPolicy {
+    /// Read a policy written like the ones in the [module docs](self).
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let params = if params.is_empty() {
+            vec![]
+        } else {
+            params
+                .split(',')
+                .map(|param| param.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| {
+                    format!("Expected numbers after '{name}:', got '{s}'")
+                })?
+        };
+        let millis = |i: usize| millis(params[i], s);
+        let expect = |count: usize| {
+            if params.len() == count {
+                Ok(())
+            } else {
+                Err(format!("'{name}' takes {count} parameters, got '{s}'"))
+            }
+        };
+
+        let policy = match name {
+            "none" => {
+                expect(0)?;
+                Self::None
+            }
+            "yield" => {
+                expect(0)?;
+                Self::Yield
+            }
+            "fixed" => {
+                expect(1)?;
+                Self::Fixed(millis(0)?)
+            }
+            "exp" => {
+                expect(2)?;
+                Self::Exponential {
+                    base: millis(0)?,
+                    max: millis(1)?,
+                }
+            }
+            "random" => {
+                expect(2)?;
+                Self::Random {
+                    min: millis(0)?,
+                    max: millis(1)?,
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown backoff '{name}', expected none, yield, fixed, \
+                     exp or random"
+                ))
+            }
+        };
+        policy.validate().map_err(|e| format!("{e} in '{s}'"))?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Exponential { base, .. } if base.is_zero() => {
+                Err("The first backoff must be more than zero".to_string())
+            }
+            Self::Exponential { base, max } if base > max => {
+                Err("The first backoff can't be more than the most".to_string())
+            }
+            Self::Random { min, max } if min >= max => {
+                Err("The minimum must be less than the maximum".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for BackoffPolicy {
+    /// Written the same way [`BackoffPolicy::parse`] reads it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: &Duration| as_millis(*duration);
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Yield => write!(f, "yield"),
+            Self::Fixed(duration) => write!(f, "fixed:{}", ms(duration)),
+            Self::Exponential { base, max } => {
+                write!(f, "exp:{},{}", ms(base), ms(max))
+            }
+            Self::Random { min, max } => {
+                write!(f, "random:{},{}", ms(min), ms(max))
+            }
+        }
+    }
+}
+
+/// One philosopher backing off with a [`BackoffPolicy`]. Keeps count of how
+/// many times in a row they've failed, until [`Backoff::reset`].
+#[derive(Debug)]
+pub struct Backoff {
+    policy: BackoffPolicy,
+    failures: u32,
+    rng: StdRng,
+}
+
+impl Backoff {
+    /// Backing off for philosopher `id`. The dice for the jitter come from
+    /// `seed` too, but not the same ones as the philosopher's durations, so
+    /// changing the policy doesn't change how long anyone thinks or eats.
+    pub fn new(policy: BackoffPolicy, seed: Option<u64>, id: usize) -> Self {
+        Self {
+            policy,
+            failures: 0,
+            rng: philosopher_rng(seed.map(|seed| !seed), id),
+        }
+    }
+
+    /// Back off after failing to pick up the forks, before trying again.
+    pub fn wait(&mut self, clock: &Clock) {
+        match self.policy {
+            BackoffPolicy::None => clock.spin(),
+            BackoffPolicy::Yield => clock.yield_now(),
+            _ => {
+                let delay = self.next_delay();
+                clock.sleep(delay);
+            }
+        }
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// The forks were picked up, so the next failure is the first again.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+
+    /// How long to sleep for this time round.
+    fn next_delay(&mut self) -> Duration {
+        match self.policy {
+            BackoffPolicy::None | BackoffPolicy::Yield => Duration::ZERO,
+            BackoffPolicy::Fixed(duration) => duration,
+            BackoffPolicy::Exponential { base, max } => {
+                let doubled = base.saturating_mul(1 << self.failures.min(31));
+                let ceiling = doubled.min(max);
+                ceiling.mul_f64(self.rng.gen_range(0.5..=1.0))
+            }
+            BackoffPolicy::Random { min, max } => self.rng.gen_range(min..max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_are_written_the_way_they_are_parsed() {
+        for s in ["none", "yield", "fixed:1", "exp:0.1,10", "random:0.5,2"] {
+            assert_eq!(BackoffPolicy::parse(s).unwrap().to_string(), s);
+        }
+        for s in [
+            "sleep:1",
+            "fixed",
+            "exp:10,1",
+            "exp:0,1",
+            "random:2,1",
+            "fixed:1e30",
+            "exp:1,1e30",
+            "random:0,inf",
+        ] {
+            assert!(BackoffPolicy::parse(s).is_err(), "{s} was accepted");
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_doubles_up_to_the_most_and_resets() {
+        let policy = BackoffPolicy::parse("exp:1,10").unwrap();
+        let mut backoff = Backoff::new(policy, Some(1), 1);
+        let ms = Duration::from_millis;
+        for ceiling in [1, 2, 4, 8, 10, 10] {
+            let delay = backoff.next_delay();
+            assert!(
+                (ms(ceiling) / 2..=ms(ceiling)).contains(&delay),
+                "{delay:?} isn't up to {ceiling}ms"
+            );
+            backoff.failures += 1;
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= ms(1));
+    }
+}
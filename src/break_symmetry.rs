@@ -1,15 +1,13 @@
-#![allow(unused_imports)]
+use crate::clock::JoinHandle;
+use crate::deadlock::Forks;
 use crate::{
-    Diner, PhilosopherState, StateMsg, HUNGER_THRESHOLD_MILLIS,
-    MAX_DURATION_MILLIS, MIN_DURATION_MILLIS, N_PHILOSOPHERS,
+    philosopher_rng, Activity, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
 };
-use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex, MutexGuard};
-use std::thread::{sleep, JoinHandle};
-use std::time::Duration;
-use std::time::Instant;
+use std::sync::Arc;
 
 /// Probably the simplest solution. You break the symmetry, and therefore the
 /// wait-for-cycle, by swapping the forks round for one philosopher. Instead
@@ -17,24 +15,29 @@ use std::time::Instant;
 /// because it makes more sense to me in the model (because you can't swap the
 /// actual forks for only one philosopher without it affecting the two
 /// philosophers next to them?)
-pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
-    let forks: Vec<Arc<Mutex<Fork>>> = (0..N_PHILOSOPHERS)
-        .map(|_| Arc::new(Mutex::new(Fork)))
-        .collect();
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let n_philosophers = config.n_philosophers;
+    let forks =
+        Arc::new(Forks::new(n_philosophers, clock.clone(), sink.clone()));
 
     let mut philosophers = vec![];
-    for i in 1..N_PHILOSOPHERS + 1 {
+    for i in 1..n_philosophers + 1 {
         let left_handed = i == 1;
-        let left_fork = forks[(i - 1) % N_PHILOSOPHERS].clone();
-        let right_fork = forks[i % N_PHILOSOPHERS].clone();
         let philosopher = Philosopher::new(
             i,
-            left_fork.clone(),
-            right_fork.clone(),
-            tx.clone(),
+            forks.clone(),
+            (i - 1) % n_philosophers,
+            i % n_philosophers,
+            clock.clone(),
+            sink.clone(),
             kill_switch.clone(),
             left_handed,
-            random,
+            config.clone(),
         );
         philosophers.push(philosopher);
     }
@@ -42,7 +45,7 @@ pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
     for mut philosopher in philosophers {
-        let handle = std::thread::spawn(move || {
+        let handle = clock.spawn(move || {
             philosopher.run();
         });
         handles.push(handle);
@@ -53,90 +56,148 @@ pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
     }
 }
 
-struct Fork;
+/// See [`main`].
+pub struct BreakSymmetry;
+
+impl Strategy for BreakSymmetry {
+    fn name(&self) -> &'static str {
+        "break_symmetry"
+    }
+
+    fn description(&self) -> &'static str {
+        "One left-handed philosopher breaks the wait-for cycle"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: false,
+            centralised: false,
+        }
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
 
 struct Philosopher {
     id: usize,
     state: PhilosopherState,
-    left_fork: Arc<Mutex<Fork>>,
-    right_fork: Arc<Mutex<Fork>>,
-    tx: Sender<StateMsg>,
+    forks: Arc<Forks>,
+    left_fork: usize,
+    right_fork: usize,
+    clock: Clock,
+    sink: EventSink,
     kill_switch: Arc<AtomicBool>,
     left_handed: bool,
-    random: bool,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
 }
 
 impl Philosopher {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: usize,
-        left_fork: Arc<Mutex<Fork>>,
-        right_fork: Arc<Mutex<Fork>>,
-        tx: Sender<StateMsg>,
+        forks: Arc<Forks>,
+        left_fork: usize,
+        right_fork: usize,
+        clock: Clock,
+        sink: EventSink,
         kill_switch: Arc<AtomicBool>,
         left_handed: bool,
-        random: bool,
+        config: SimConfig,
     ) -> Self {
         Self {
             id,
             state: PhilosopherState::Thinking,
+            forks,
             left_fork,
             right_fork,
-            tx,
+            clock,
+            sink,
             kill_switch,
             left_handed,
-            random,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            config,
         }
     }
 }
 
 impl Diner for Philosopher {
-    fn send_state(&self) {
-        self.tx
-            .send(StateMsg {
-                id: self.id,
-                state: self.current_state(),
-            })
-            .expect("Error when sending state.");
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
     }
 
     fn think(&mut self) {
         log::debug!("Philosopher {} is thinking", self.id);
         self.state = PhilosopherState::Thinking;
-        self.sleep(self.random);
+        self.emit(EventKind::StartedThinking);
+        self.sleep(Activity::Thinking);
 
         log::debug!("Philosopher {} is hungry", self.id);
-        self.state = PhilosopherState::Hungry(Instant::now());
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
     }
 
     fn eat(&mut self) {
         while let PhilosopherState::Hungry(_) = self.state {
-            // Pick up left fork first if left handed. MutexGuards are assigned
-            // to variables outside of if-else statement to keep them in scope.
-            let (_left, _right): (MutexGuard<Fork>, MutexGuard<Fork>) =
-                if self.left_handed {
-                    let left = self.left_fork.lock();
-                    let right = self.right_fork.lock();
-                    (left.unwrap(), right.unwrap())
-                } else {
-                    let right = self.right_fork.lock();
-                    let left = self.left_fork.lock();
-                    (left.unwrap(), right.unwrap())
-                };
+            // Pick up left fork first if left handed. The guards are assigned
+            // to variables to keep them in scope while eating, and put the
+            // forks down again when they're dropped.
+            let (first, second) = if self.left_handed {
+                (self.left_fork, self.right_fork)
+            } else {
+                (self.right_fork, self.left_fork)
+            };
+            let forks = self.forks.clone();
+            let kill_switch = self.kill_switch.clone();
+            let Some(_first) = forks.pick_up(self.id, first, &kill_switch)
+            else {
+                break;
+            };
+            let Some(_second) = forks.pick_up(self.id, second, &kill_switch)
+            else {
+                break;
+            };
             if !self.has_starved_to_death() {
                 // Philosopher has successfully picked up both forks and will
                 // start to eat, as long as they're not dead.
                 log::debug!("Philosopher {} is eating", self.id);
                 self.state = PhilosopherState::Eating;
-                self.sleep(self.random);
-                self.send_state();
+                self.emit(EventKind::StartedEating);
+                self.sleep(Activity::Eating);
+                self.emit(EventKind::FinishedEating);
                 log::debug!("Philosopher {} is full", self.id);
             } else {
                 self.state = PhilosopherState::Dead;
-                self.send_state();
+                self.emit(EventKind::Died);
             }
         }
     }
 
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
     fn current_state(&self) -> PhilosopherState {
         self.state
     }
@@ -0,0 +1,442 @@
+use crate::clock::JoinHandle;
+use crate::{
+    philosopher_rng, Activity, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
+};
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// How long a hungry philosopher waits for a message before checking whether
+/// they've starved or the kill switch has been activated.
+const WAIT_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Chandy and Misra's "hygienic" solution. There's no shared state at all,
+/// each fork is owned by exactly one philosopher and gets passed between
+/// neighbours as a message. Forks are either clean or dirty: a fork becomes
+/// dirty once it's been eaten with, and a philosopher only has to give up a
+/// fork when asked if it's dirty. A fork is cleaned when it's handed over.
+///
+/// Initially every fork is dirty and owned by the philosopher with the lower
+/// id, which means the "who has priority" graph starts off acyclic so it can
+/// never deadlock, and because a philosopher who has just eaten has to hand
+/// over their dirty forks, nobody can starve either.
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let philosophers =
+        seat_philosophers(clock.clone(), sink, kill_switch, config);
+
+    let mut handles: Vec<JoinHandle<()>> = vec![];
+
+    for mut philosopher in philosophers {
+        let handle = clock.spawn(move || {
+            philosopher.report_initial_forks();
+            philosopher.run();
+        });
+        handles.push(handle);
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+/// See [`main`].
+pub struct ChandyMisra;
+
+impl Strategy for ChandyMisra {
+    fn name(&self) -> &'static str {
+        "chandy_misra"
+    }
+
+    fn description(&self) -> &'static str {
+        "Clean and dirty forks passed between neighbours as messages"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: true,
+            centralised: false,
+        }
+    }
+
+    fn title(&self) -> String {
+        "CHANDY-MISRA".to_string()
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
+
+fn seat_philosophers(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) -> Vec<Philosopher> {
+    let n_philosophers = config.n_philosophers;
+    let (msg_txs, msg_rxs): (Vec<Sender<Message>>, Vec<Receiver<Message>>) =
+        (0..n_philosophers).map(|_| mpsc::channel()).unzip();
+
+    let mut philosophers = vec![];
+    for (i, msg_rx) in (1..n_philosophers + 1).zip(msg_rxs) {
+        // Fork `i - 1` is shared with the philosopher on the left, fork `i`
+        // with the one on the right.
+        let left_neighbour = (i + n_philosophers - 2) % n_philosophers + 1;
+        let right_neighbour = i % n_philosophers + 1;
+        let left_fork = ForkSlot::new(
+            (i - 1) % n_philosophers,
+            msg_txs[left_neighbour - 1].clone(),
+            i < left_neighbour,
+        );
+        let right_fork = ForkSlot::new(
+            i % n_philosophers,
+            msg_txs[right_neighbour - 1].clone(),
+            i < right_neighbour,
+        );
+        let philosopher = Philosopher::new(
+            i,
+            [left_fork, right_fork],
+            msg_rx,
+            clock.clone(),
+            sink.clone(),
+            kill_switch.clone(),
+            config.clone(),
+        );
+        philosophers.push(philosopher);
+    }
+    philosophers
+}
+
+/// Messages sent between neighbours, identified by the fork they're about
+/// rather than who sent them (with two philosophers both forks are shared with
+/// the same neighbour).
+enum Message {
+    /// The request token for a fork, asking the owner to hand it over.
+    Request(usize),
+    /// The fork itself.
+    Fork(usize),
+}
+
+/// A philosopher's view of one of the two forks next to them.
+struct ForkSlot {
+    fork: usize,
+    neighbour: Sender<Message>,
+    held: bool,
+    dirty: bool,
+    /// The neighbour has asked for this fork and is still waiting for it.
+    requested: bool,
+    /// This philosopher has asked for this fork and is still waiting for it.
+    asked: bool,
+}
+
+impl ForkSlot {
+    pub fn new(fork: usize, neighbour: Sender<Message>, held: bool) -> Self {
+        Self {
+            fork,
+            neighbour,
+            held,
+            dirty: true,
+            requested: false,
+            asked: false,
+        }
+    }
+
+    fn send(&self, message: Message) {
+        // The neighbour only stops listening once the kill switch is active,
+        // at which point it doesn't matter if the message is lost.
+        let _ = self.neighbour.send(message);
+    }
+}
+
+struct Philosopher {
+    id: usize,
+    state: PhilosopherState,
+    forks: [ForkSlot; 2],
+    msg_rx: Receiver<Message>,
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
+}
+
+impl Philosopher {
+    pub fn new(
+        id: usize,
+        forks: [ForkSlot; 2],
+        msg_rx: Receiver<Message>,
+        clock: Clock,
+        sink: EventSink,
+        kill_switch: Arc<AtomicBool>,
+        config: SimConfig,
+    ) -> Self {
+        Self {
+            id,
+            state: PhilosopherState::Thinking,
+            forks,
+            msg_rx,
+            clock,
+            sink,
+            kill_switch,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            config,
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Fork(fork) => {
+                let slot = self.slot_mut(fork);
+                slot.held = true;
+                slot.dirty = false;
+                slot.asked = false;
+                self.emit(EventKind::AcquiredFork(fork));
+            }
+            Message::Request(fork) => {
+                self.slot_mut(fork).requested = true;
+                self.hand_over_dirty_forks();
+            }
+        }
+    }
+
+    fn slot_mut(&mut self, fork: usize) -> &mut ForkSlot {
+        self.forks
+            .iter_mut()
+            .find(|slot| slot.fork == fork)
+            .expect("Message about a fork that isn't next to this philosopher.")
+    }
+
+    /// Give any dirty forks to neighbours that have asked for them. Never
+    /// called while eating, so every held fork is free to be handed over.
+    fn hand_over_dirty_forks(&mut self) {
+        let hungry = matches!(self.state, PhilosopherState::Hungry(_));
+        for slot in self.forks.iter_mut() {
+            if slot.held && slot.dirty && slot.requested {
+                slot.held = false;
+                slot.requested = false;
+                self.sink.emit(self.id, EventKind::ReleasedFork(slot.fork));
+                slot.send(Message::Fork(slot.fork));
+                log::debug!(
+                    "Philosopher {} handed over fork {}",
+                    self.id,
+                    slot.fork
+                );
+                // A hungry philosopher still needs the fork back, so the
+                // request token goes straight back with it.
+                if hungry {
+                    slot.asked = true;
+                    slot.send(Message::Request(slot.fork));
+                }
+            }
+        }
+    }
+
+    /// Report the forks the philosopher starts off with.
+    fn report_initial_forks(&self) {
+        for slot in self.forks.iter().filter(|slot| slot.held) {
+            self.emit(EventKind::AcquiredFork(slot.fork));
+        }
+    }
+
+    fn request_missing_forks(&mut self) {
+        for slot in self.forks.iter_mut() {
+            if !slot.held && !slot.asked {
+                slot.asked = true;
+                slot.send(Message::Request(slot.fork));
+            }
+        }
+    }
+}
+
+impl Diner for Philosopher {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
+    }
+
+    fn think(&mut self) {
+        log::debug!("Philosopher {} is thinking", self.id);
+        self.state = PhilosopherState::Thinking;
+        self.emit(EventKind::StartedThinking);
+
+        // Neighbours can ask for forks while this philosopher is thinking, so
+        // instead of sleeping they keep answering messages until it's time to
+        // get hungry.
+        let finished_at =
+            self.clock.now() + self.generate_duration(Activity::Thinking);
+        while let Some(remaining) = finished_at.checked_sub(self.clock.now()) {
+            match self.clock.recv_timeout(&self.msg_rx, remaining) {
+                Ok(message) => self.handle(message),
+                Err(_) => break,
+            }
+        }
+
+        log::debug!("Philosopher {} is hungry", self.id);
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
+    }
+
+    fn eat(&mut self) {
+        self.request_missing_forks();
+        while let PhilosopherState::Hungry(_) = self.state {
+            if self.forks.iter().all(|slot| slot.held) {
+                // Philosopher has been handed both forks and will start to
+                // eat. Any requests that arrive in the meantime have to wait.
+                log::debug!("Philosopher {} is eating", self.id);
+                self.state = PhilosopherState::Eating;
+                self.emit(EventKind::StartedEating);
+                self.sleep(Activity::Eating);
+                self.emit(EventKind::FinishedEating);
+                log::debug!("Philosopher {} is full", self.id);
+                for slot in self.forks.iter_mut() {
+                    slot.dirty = true;
+                }
+                self.hand_over_dirty_forks();
+            } else {
+                match self.clock.recv_timeout(&self.msg_rx, WAIT_INTERVAL) {
+                    Ok(message) => self.handle(message),
+                    Err(_) if self.has_starved_to_death() => {
+                        self.state = PhilosopherState::Dead;
+                        self.emit(EventKind::Died);
+                    }
+                    Err(_) if self.is_kill_switch_active() => break,
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
+    fn current_state(&self) -> PhilosopherState {
+        self.state
+    }
+
+    fn is_kill_switch_active(&self) -> bool {
+        self.kill_switch.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{self, Outcome};
+    use crate::invariants::Checker;
+
+    fn seat(n_philosophers: usize) -> Vec<Philosopher> {
+        let clock = Clock::real();
+        let (sink, _rx) = EventSink::channel(clock.clone(), n_philosophers);
+        let config = SimConfig {
+            n_philosophers,
+            ..SimConfig::default()
+        };
+        seat_philosophers(clock, sink, Arc::new(AtomicBool::new(false)), config)
+    }
+
+    #[test]
+    fn the_lower_id_owns_each_fork_at_first() {
+        for n_philosophers in [2, 5, 7] {
+            let philosophers = seat(n_philosophers);
+            for fork in 0..n_philosophers {
+                // Fork 0 is shared by the first and last philosophers, and
+                // every other fork by the philosopher with the same number
+                // and the one after them.
+                let owners: Vec<usize> = philosophers
+                    .iter()
+                    .filter(|philosopher| {
+                        philosopher.forks.iter().any(|slot| {
+                            slot.fork == fork && slot.held && slot.dirty
+                        })
+                    })
+                    .map(|philosopher| philosopher.id)
+                    .collect();
+                assert_eq!(owners, [fork.max(1)], "fork {fork}");
+            }
+        }
+    }
+
+    #[test]
+    fn only_dirty_forks_are_given_up() {
+        let mut philosophers = seat(5);
+        let first = &mut philosophers[0];
+        first.slot_mut(1).dirty = false;
+        first.handle(Message::Request(0));
+        first.handle(Message::Request(1));
+        assert!(!first.slot_mut(0).held);
+        assert!(first.slot_mut(1).held && first.slot_mut(1).requested);
+        assert!(matches!(
+            philosophers[4].msg_rx.try_recv(),
+            Ok(Message::Fork(0))
+        ));
+        assert!(philosophers[1].msg_rx.try_recv().is_err());
+
+        // Once it's been eaten with, the fork goes to whoever asked for it.
+        let first = &mut philosophers[0];
+        first.slot_mut(1).dirty = true;
+        first.hand_over_dirty_forks();
+        assert!(!first.slot_mut(1).held && !first.slot_mut(1).requested);
+        assert!(matches!(
+            philosophers[1].msg_rx.try_recv(),
+            Ok(Message::Fork(1))
+        ));
+    }
+
+    #[test]
+    fn forks_are_cleaned_when_handed_over() {
+        let mut philosophers = seat(5);
+        let last = &mut philosophers[4];
+        assert!(!last.slot_mut(0).held);
+        last.handle(Message::Fork(0));
+        assert!(last.slot_mut(0).held && !last.slot_mut(0).dirty);
+    }
+
+    #[test]
+    fn everyone_eats_on_a_virtual_clock() {
+        let config = SimConfig {
+            run_time_seconds: 2,
+            random: true,
+            virtual_time: true,
+            seed: Some(3),
+            ..SimConfig::default()
+        };
+        let (report, events) = harness::run_recorded(&ChandyMisra, &config);
+        assert_eq!(report.outcome, Outcome::Completed);
+        assert!(report.meals.iter().all(|&meals| meals > 0), "{report:?}");
+
+        let mut checker = Checker::new(config.n_philosophers);
+        for event in &events {
+            if let Err(violation) = checker.check(event) {
+                panic!("{violation}");
+            }
+        }
+    }
+}
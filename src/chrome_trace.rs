@@ -0,0 +1,172 @@
+//! Exporting a run in the Chrome Trace Event Format, which can be loaded
+//! into Perfetto (<https://ui.perfetto.dev>) or `chrome://tracing`.
+//!
+//! Every philosopher gets a track with their thinking, hungry and eating
+//! spans as slices, and every fork gets a track showing who was holding it.
+
+use crate::timeline::Timeline;
+use crate::trace::{escape_json, TraceHeader};
+use crate::Event;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Trace viewers group tracks by process, so philosophers and forks are
+/// shown as two pretend processes.
+const PHILOSOPHERS_PID: usize = 1;
+const FORKS_PID: usize = 2;
+
+/// Write a Chrome trace of the run to `path`.
+pub fn save(
+    path: &Path,
+    header: &TraceHeader,
+    events: &[Event],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_chrome_trace(&mut writer, header, events)?;
+    writer.flush()
+}
+
+pub fn write_chrome_trace<W: Write>(
+    writer: &mut W,
+    header: &TraceHeader,
+    events: &[Event],
+) -> io::Result<()> {
+    let n_philosophers = header.config.n_philosophers;
+    let timeline = Timeline::from_events(n_philosophers, events);
+    let mut trace_events = vec![
+        metadata(PHILOSOPHERS_PID, None, "process_name", "Philosophers"),
+        metadata(FORKS_PID, None, "process_name", "Forks"),
+    ];
+
+    for (seat, spans) in timeline.philosophers.iter().enumerate() {
+        let id = seat + 1;
+        trace_events.push(metadata(
+            PHILOSOPHERS_PID,
+            Some(id),
+            "thread_name",
+            &format!("Philosopher {id}"),
+        ));
+        for span in spans {
+            trace_events.push(slice(
+                PHILOSOPHERS_PID,
+                id,
+                span.phase.name(),
+                span.start,
+                span.end,
+            ));
+        }
+        if let Some(at) = timeline.deaths[seat] {
+            trace_events.push(format!(
+                "{{\"ph\":\"i\",\"pid\":{PHILOSOPHERS_PID},\"tid\":{id},\
+                 \"name\":\"died\",\"s\":\"t\",\"ts\":{}}}",
+                at.as_micros()
+            ));
+        }
+    }
+
+    for (fork, holds) in timeline.forks.iter().enumerate() {
+        trace_events.push(metadata(
+            FORKS_PID,
+            Some(fork),
+            "thread_name",
+            &format!("Fork {fork}"),
+        ));
+        for hold in holds {
+            trace_events.push(slice(
+                FORKS_PID,
+                fork,
+                &format!("philosopher {}", hold.holder),
+                hold.start,
+                hold.end,
+            ));
+        }
+    }
+
+    let seed = header
+        .seed
+        .map_or_else(|| "null".to_string(), |seed| seed.to_string());
+    writeln!(writer, "{{\"displayTimeUnit\":\"ms\",")?;
+    writeln!(
+        writer,
+        "\"otherData\":{{\"strategy\":\"{}\",\"seed\":{seed},\
+         \"n_philosophers\":{n_philosophers},\"random\":{}}},",
+        escape_json(&header.strategy),
+        header.config.random
+    )?;
+    writeln!(writer, "\"traceEvents\":[")?;
+    for (i, trace_event) in trace_events.iter().enumerate() {
+        let separator = if i + 1 < trace_events.len() { "," } else { "" };
+        writeln!(writer, "{trace_event}{separator}")?;
+    }
+    writeln!(writer, "]}}")
+}
+
+/// A complete ("X") event, i.e. a slice with a start and a duration.
+fn slice(
+    pid: usize,
+    tid: usize,
+    name: &str,
+    start: Duration,
+    end: Duration,
+) -> String {
+    format!(
+        "{{\"ph\":\"X\",\"pid\":{pid},\"tid\":{tid},\"name\":\"{name}\",\
+         \"ts\":{},\"dur\":{}}}",
+        start.as_micros(),
+        end.saturating_sub(start).as_micros()
+    )
+}
+
+/// A metadata ("M") event naming a process or, given a `tid`, a track.
+fn metadata(pid: usize, tid: Option<usize>, kind: &str, name: &str) -> String {
+    let tid = tid.map_or_else(String::new, |tid| format!("\"tid\":{tid},"));
+    format!(
+        "{{\"ph\":\"M\",\"pid\":{pid},{tid}\"name\":\"{kind}\",\
+         \"args\":{{\"name\":\"{name}\"}}}}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::tests::two_meals;
+
+    #[test]
+    fn every_meal_is_a_slice_and_every_fork_a_track() {
+        let (header, events) = two_meals();
+        let mut out = Vec::new();
+        write_chrome_trace(&mut out, &header, &events).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // Every trace event is on a line of its own.
+        let trace_events: Vec<_> =
+            out.lines().map(|line| line.trim_end_matches(',')).collect();
+
+        let meals: Vec<_> = trace_events
+            .iter()
+            .filter(|e| e.contains("\"name\":\"eating\""))
+            .copied()
+            .collect();
+        assert_eq!(
+            meals,
+            [
+                "{\"ph\":\"X\",\"pid\":1,\"tid\":1,\"name\":\"eating\",\
+                 \"ts\":6000,\"dur\":10000}",
+                "{\"ph\":\"X\",\"pid\":1,\"tid\":2,\"name\":\"eating\",\
+                 \"ts\":17000,\"dur\":13000}",
+            ]
+        );
+
+        let track_names: Vec<_> = trace_events
+            .iter()
+            .filter(|e| e.contains("\"name\":\"thread_name\""))
+            .filter_map(|e| e.split("\"args\":{\"name\":\"").nth(1))
+            .map(|name| name.trim_end_matches("\"}}"))
+            .collect();
+        assert_eq!(
+            track_names,
+            ["Philosopher 1", "Philosopher 2", "Fork 0", "Fork 1"]
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use dining_philosophers::stats::Test;
+use dining_philosophers::svg::Window;
+use dining_philosophers::{
+    Activity, BackoffPolicy, DurationRule, Registry, SimConfig, Strategy,
+};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: dining_philosophers [OPTIONS]
+       dining_philosophers replay <TRACE> [REPLAY OPTIONS]
+
+Runs each selected strategy with fixed and/or random timing and prints how
+many meals every philosopher managed to eat. `replay` counts the meals in a
+trace saved with --trace instead of running anything.
+
+Options:
+  -s, --strategy <NAME>         Strategy to run, can be repeated or comma
+                                separated (default: all of them)
+      --fixed                   Only run with fixed timing
+      --random                  Only run with random timing
+  -n, --philosophers <N>        Number of philosophers at the table
+  -t, --run-time <SECONDS>      How long to run each strategy for
+  -k, --trials <K>              Run each strategy K times and print summary
+                                statistics instead of a single result
+      --trace <PATH>            Save every event to a trace file, binary if
+                                PATH ends in .bin and JSON lines otherwise.
+                                With more than one run, the strategy, timing
+                                and trial are added to the file name
+      --chrome-trace <PATH>     Save each run's timeline in the Chrome trace
+                                event format, for Perfetto or
+                                chrome://tracing
+      --svg <PATH>              Draw each run as an SVG Gantt chart
+      --svg-window <START:END>  Only draw this part of the run, in
+                                milliseconds (e.g. 1000:1500)
+      --visualize               Draw the table in the terminal while each
+                                run is going
+      --test <TEST>             How to compare strategies when running more
+                                than one trial, `welch` or `mann-whitney`
+                                (default: welch)
+      --seed <SEED>             Where random durations come from, to repeat
+                                earlier runs (default: a new one each time).
+                                Trial K uses SEED + K - 1
+      --virtual-time            Run on a simulated clock instead of really
+                                waiting, which is much faster and gives the
+                                same results every time with fixed timing
+      --min-duration <MILLIS>   Shortest time spent thinking or eating
+                                (default: a tenth of the maximum)
+      --max-duration <MILLIS>   Longest time spent thinking or eating
+      --think [ID=]<DIST>       How long to think for with random timing
+                                instead of anything from the minimum to the
+                                maximum duration, e.g. exp:5, lognormal:5,1
+                                or file:PATH. With ID, only for that
+                                philosopher. Can be repeated
+      --eat [ID=]<DIST>         How long to eat for with random timing, like
+                                --think
+      --hunger-threshold <MILLIS>
+                                How long a philosopher can be hungry for
+                                before they starve (default: ten times the
+                                maximum duration)
+      --backoff <POLICY>        What to do after failing to pick up both
+                                forks in the strategies that poll for them:
+                                none, yield, fixed:MS, exp:BASE,MAX or
+                                random:MIN,MAX (default: none)
+  -l, --list                    List the available strategies
+  -h, --help                    Print this message
+
+Replay options:
+      --visualize               Draw the table in the terminal as the trace
+                                plays back
+      --speed <X>               Play back X times faster than the run went
+                                (default: 1)";
+
+pub enum Command<'r> {
+    Run(Box<RunArgs<'r>>),
+    Replay(ReplayArgs),
+    List,
+    Help,
+}
+
+pub struct RunArgs<'r> {
+    pub strategies: Vec<&'r dyn Strategy>,
+    /// Which timings to run each strategy with, `false` meaning fixed and
+    /// `true` meaning random.
+    pub timings: Vec<bool>,
+    pub config: SimConfig,
+    pub trials: usize,
+    /// How to compare strategies' throughput when there's more than one
+    /// trial.
+    pub test: Test,
+    /// Where to save trace files, if anywhere.
+    pub trace: Option<PathBuf>,
+    /// Where to save Chrome trace files, if anywhere.
+    pub chrome_trace: Option<PathBuf>,
+    /// Where to save Gantt charts, if anywhere.
+    pub svg: Option<PathBuf>,
+    /// The part of each run the Gantt charts show, all of it if `None`.
+    pub svg_window: Option<Window>,
+    /// Whether to draw the table live in the terminal.
+    pub visualize: bool,
+}
+
+pub struct ReplayArgs {
+    pub trace: PathBuf,
+    /// Whether to draw the table live in the terminal.
+    pub visualize: bool,
+    /// How many times faster than real time to draw the table.
+    pub speed: f64,
+}
+
+/// Parse the command line arguments, not including the program name.
+/// Strategies are looked up in `registry`.
+pub fn parse<'r, I>(
+    registry: &'r Registry,
+    args: I,
+) -> Result<Command<'r>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    if args.next_if(|arg| arg == "replay").is_some() {
+        return parse_replay(args);
+    }
+    let mut strategies: Vec<&'r dyn Strategy> = vec![];
+    let mut fixed = false;
+    let mut random = false;
+    let mut config = SimConfig::default();
+    // Unless they're given, these follow the maximum duration.
+    let mut min_duration = None;
+    let mut hunger_threshold = None;
+    let mut trials = 1;
+    let mut test = Test::Welch;
+    let mut trace = None;
+    let mut chrome_trace = None;
+    let mut svg = None;
+    let mut svg_window = None;
+    let mut visualize = false;
+
+    while let Some((flag, inline_value)) = next_flag(&mut args) {
+        let mut value = || flag_value(&flag, &inline_value, &mut args);
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-l" | "--list" => return Ok(Command::List),
+            "-s" | "--strategy" => {
+                for name in value()?.split(',') {
+                    let strategy = find_strategy(registry, name.trim())?;
+                    if !strategies.iter().any(|s| s.name() == strategy.name()) {
+                        strategies.push(strategy);
+                    }
+                }
+            }
+            "--fixed" => fixed = true,
+            "--random" => random = true,
+            "-n" | "--philosophers" => {
+                config.n_philosophers = parse_number(&flag, &value()?)?
+            }
+            "-t" | "--run-time" => {
+                config.run_time_seconds = parse_number(&flag, &value()?)?
+            }
+            "-k" | "--trials" => trials = parse_number(&flag, &value()?)?,
+            "--test" => test = parse_test(&value()?)?,
+            "--trace" => trace = Some(PathBuf::from(value()?)),
+            "--chrome-trace" => chrome_trace = Some(PathBuf::from(value()?)),
+            "--svg" => svg = Some(PathBuf::from(value()?)),
+            "--svg-window" => svg_window = Some(Window::parse(&value()?)?),
+            "--visualize" => visualize = true,
+            "--virtual-time" => config.virtual_time = true,
+            "--seed" => config.seed = Some(parse_number(&flag, &value()?)?),
+            "--min-duration" => {
+                min_duration = Some(parse_number(&flag, &value()?)?)
+            }
+            "--think" => config
+                .durations
+                .push(DurationRule::parse(Activity::Thinking, &value()?)?),
+            "--eat" => config
+                .durations
+                .push(DurationRule::parse(Activity::Eating, &value()?)?),
+            "--max-duration" => {
+                config.max_duration_millis = parse_number(&flag, &value()?)?
+            }
+            "--hunger-threshold" => {
+                hunger_threshold = Some(parse_number(&flag, &value()?)?)
+            }
+            "--backoff" => config.backoff = BackoffPolicy::parse(&value()?)?,
+            _ => return Err(format!("Unknown option '{flag}'")),
+        }
+    }
+
+    config.min_duration_millis =
+        min_duration.unwrap_or(config.max_duration_millis / 10);
+    config.hunger_threshold_millis = hunger_threshold
+        .unwrap_or(config.max_duration_millis.saturating_mul(10));
+    config.validate()?;
+    if trials == 0 {
+        return Err("There must be at least one trial".to_string());
+    }
+
+    if strategies.is_empty() {
+        strategies = registry.iter().collect();
+    }
+    let timings = match (fixed, random) {
+        (true, false) => vec![false],
+        (false, true) => vec![true],
+        _ => vec![false, true],
+    };
+
+    Ok(Command::Run(Box::new(RunArgs {
+        strategies,
+        timings,
+        config,
+        trials,
+        test,
+        trace,
+        chrome_trace,
+        svg,
+        svg_window,
+        visualize,
+    })))
+}
+
+fn parse_replay<'r, I>(mut args: I) -> Result<Command<'r>, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut trace = None;
+    let mut visualize = false;
+    let mut speed: f64 = 1.0;
+
+    while let Some((flag, inline_value)) = next_flag(&mut args) {
+        let mut value = || flag_value(&flag, &inline_value, &mut args);
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--visualize" => visualize = true,
+            "--speed" => speed = parse_number(&flag, &value()?)?,
+            _ if !flag.starts_with('-') && trace.is_none() => {
+                trace = Some(PathBuf::from(flag))
+            }
+            _ => return Err(format!("Unknown replay option '{flag}'")),
+        }
+    }
+
+    let trace = trace.ok_or("replay needs a trace file")?;
+    if !speed.is_finite() || speed <= 0.0 {
+        return Err("The replay speed must be more than zero".to_string());
+    }
+    Ok(Command::Replay(ReplayArgs {
+        trace,
+        visualize,
+        speed,
+    }))
+}
+
+/// The next flag, split from its value if it's given as `--option=value`
+/// rather than `--option value`.
+fn next_flag<I>(args: &mut I) -> Option<(String, Option<String>)>
+where
+    I: Iterator<Item = String>,
+{
+    let arg = args.next()?;
+    Some(match arg.split_once('=') {
+        Some((flag, value)) if flag.starts_with("--") => {
+            (flag.to_string(), Some(value.to_string()))
+        }
+        _ => (arg, None),
+    })
+}
+
+/// The value given with `flag`, or else the argument after it.
+fn flag_value<I>(
+    flag: &str,
+    inline_value: &Option<String>,
+    args: &mut I,
+) -> Result<String, String>
+where
+    I: Iterator<Item = String>,
+{
+    inline_value
+        .clone()
+        .or_else(|| args.next())
+        .ok_or_else(|| format!("{flag} needs a value"))
+}
+
+fn find_strategy<'r>(
+    registry: &'r Registry,
+    name: &str,
+) -> Result<&'r dyn Strategy, String> {
+    registry.get(name).ok_or_else(|| {
+        format!("Unknown strategy '{name}', use --list to see them all")
+    })
+}
+
+fn parse_test(name: &str) -> Result<Test, String> {
+    match name {
+        "welch" => Ok(Test::Welch),
+        "mann-whitney" => Ok(Test::MannWhitney),
+        _ => Err(format!(
+            "Unknown test '{name}', expected 'welch' or 'mann-whitney'"
+        )),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(
+    flag: &str,
+    value: &str,
+) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args<'r>(
+        registry: &'r Registry,
+        args: &[&str],
+    ) -> Result<Command<'r>, String> {
+        parse(registry, args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse_args(&Registry::builtin(), args) {
+            Err(e) => e,
+            Ok(_) => panic!("{args:?} was accepted"),
+        }
+    }
+
+    #[test]
+    fn options_can_be_given_either_way() {
+        let registry = Registry::builtin();
+        let Ok(Command::Run(args)) = parse_args(
+            &registry,
+            &["-n", "3", "--run-time=4", "--seed", "42", "-s", "two_forks"],
+        ) else {
+            panic!("expected a run");
+        };
+        assert_eq!(args.config.n_philosophers, 3);
+        assert_eq!(args.config.run_time_seconds, 4);
+        assert_eq!(args.config.seed, Some(42));
+        assert_eq!(args.strategies.len(), 1);
+        assert_eq!(args.strategies[0].name(), "two_forks");
+    }
+
+    #[test]
+    fn other_durations_follow_the_maximum_unless_given() {
+        let registry = Registry::builtin();
+        let config = |args: &[&str]| match parse_args(&registry, args) {
+            Ok(Command::Run(args)) => args.config,
+            _ => panic!("expected a run"),
+        };
+        let only_max = config(&["--max-duration", "50"]);
+        assert_eq!(only_max.min_duration_millis, 5);
+        assert_eq!(only_max.hunger_threshold_millis, 500);
+        let shortest = config(&["--max-duration", "1"]);
+        assert_eq!(shortest.min_duration_millis, 0);
+        assert_eq!(shortest.hunger_threshold_millis, 10);
+        let given = config(&[
+            "--min-duration",
+            "20",
+            "--max-duration",
+            "50",
+            "--hunger-threshold",
+            "100",
+        ]);
+        assert_eq!(given.min_duration_millis, 20);
+        assert_eq!(given.hunger_threshold_millis, 100);
+    }
+
+    #[test]
+    fn list_and_help_stop_parsing() {
+        let registry = Registry::builtin();
+        let parsed = |args: &[&str]| parse_args(&registry, args);
+        assert!(matches!(parsed(&["--list"]), Ok(Command::List)));
+        assert!(matches!(parsed(&["-n", "3", "-l"]), Ok(Command::List)));
+        assert!(matches!(parsed(&["-t", "5", "-h"]), Ok(Command::Help)));
+        assert!(matches!(parsed(&["replay", "-h"]), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn bad_options_are_rejected() {
+        assert_eq!(error(&["--frobnicate"]), "Unknown option '--frobnicate'");
+        assert_eq!(error(&["-t", "5", "-n"]), "-n needs a value");
+        assert_eq!(error(&["-n", "many"]), "-n expects a number, got 'many'");
+        assert!(error(&["-s", "nope"]).starts_with("Unknown strategy 'nope'"));
+        assert_eq!(
+            error(&["replay", "trace.jsonl", "--frobnicate"]),
+            "Unknown replay option '--frobnicate'"
+        );
+        assert_eq!(error(&["replay", "--speed"]), "--speed needs a value");
+        assert_eq!(error(&["replay"]), "replay needs a trace file");
+    }
+}
@@ -0,0 +1,562 @@
+//! Where the time comes from while a strategy runs.
+//!
+//! With a real clock philosophers really sleep and really block on forks and
+//! messages, so a ten second run takes ten seconds and no two runs are the
+//! same. With a virtual clock only one thread runs at a time and time only
+//! moves on when every thread is asleep or waiting, jumping straight to the
+//! next thing that's due to happen. A ten second run takes as long as the
+//! work in it, and with fixed timing every run of a strategy is identical.
+//!
+//! For that to work everything that sleeps or blocks has to go through the
+//! clock: [`Clock::sleep`] instead of `thread::sleep`, [`Clock::lock`]
+//! instead of `Mutex::lock`, [`Clock::recv_timeout`] instead of
+//! `Receiver::recv_timeout` and so on, and threads have to be started with
+//! [`Clock::spawn`]. A thread waiting on something with a virtual clock
+//! checks again whenever another thread has had a turn that changed
+//! something, so the order things happen in only depends on the timings.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvError, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, TryLockError};
+use std::thread::{self, Scope, ScopedJoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long a thread busy waiting with [`Clock::spin`] lets virtual time
+/// move on by when nothing else happens, so it still notices time passing.
+pub const SPIN_INTERVAL: Duration = Duration::from_millis(1);
+
+thread_local! {
+    /// The current thread's number in the virtual clock's schedule.
+    static PARTICIPANT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+#[derive(Clone, Debug)]
+pub struct Clock {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Real(Instant),
+    Virtual(Arc<Scheduler>),
+}
+
+impl Clock {
+    /// A clock that starts now and runs in real time.
+    pub fn real() -> Self {
+        Self {
+            kind: Kind::Real(Instant::now()),
+        }
+    }
+
+    /// A clock that starts at zero and only moves on when every thread is
+    /// waiting. The thread that creates it takes part in the schedule, and
+    /// has to call [`Clock::leave`] before waiting on anything outside it.
+    pub fn simulated() -> Self {
+        Self {
+            kind: Kind::Virtual(Arc::new(Scheduler::new())),
+        }
+    }
+
+    /// A virtual clock if `virtual_time` is set, a real one otherwise.
+    pub fn new(virtual_time: bool) -> Self {
+        if virtual_time {
+            Self::simulated()
+        } else {
+            Self::real()
+        }
+    }
+
+    /// Time since the clock started.
+    pub fn now(&self) -> Duration {
+        match &self.kind {
+            Kind::Real(start) => start.elapsed(),
+            Kind::Virtual(scheduler) => scheduler.now(),
+        }
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        match &self.kind {
+            Kind::Real(_) => thread::sleep(duration),
+            Kind::Virtual(scheduler) => {
+                let wake_at = scheduler.now() + duration;
+                scheduler.park(Status::Sleeping(wake_at));
+            }
+        }
+    }
+
+    /// Called on every go round a busy wait loop. In real time the loop just
+    /// keeps going. In virtual time nothing the loop is waiting for can
+    /// change until another thread has had a turn, so the thread waits for
+    /// that, or for [`SPIN_INTERVAL`] to pass.
+    pub fn spin(&self) {
+        if let Kind::Virtual(scheduler) = &self.kind {
+            let deadline = scheduler.now() + SPIN_INTERVAL;
+            scheduler.park(Status::Waiting(Some(deadline)));
+        }
+    }
+
+    /// Let another thread run before carrying on. With a virtual clock only
+    /// one thread runs at a time anyway, so this is the same as
+    /// [`Clock::spin`].
+    pub fn yield_now(&self) {
+        match &self.kind {
+            Kind::Real(_) => thread::yield_now(),
+            Kind::Virtual(_) => self.spin(),
+        }
+    }
+
+    /// Lock `mutex`, waiting until it's free. Panics if the mutex is
+    /// poisoned.
+    pub fn lock<'a, T>(&self, mutex: &'a Mutex<T>) -> MutexGuard<'a, T> {
+        match &self.kind {
+            Kind::Real(_) => mutex.lock().unwrap(),
+            Kind::Virtual(scheduler) => scheduler
+                .wait(None, || match mutex.try_lock() {
+                    Ok(guard) => Some(guard),
+                    Err(TryLockError::WouldBlock) => None,
+                    Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+                })
+                .expect("Waiting with no timeout only ends with the lock"),
+        }
+    }
+
+    pub fn recv<T>(&self, rx: &Receiver<T>) -> Result<T, RecvError> {
+        match &self.kind {
+            Kind::Real(_) => rx.recv(),
+            Kind::Virtual(scheduler) => scheduler
+                .wait(None, || match rx.try_recv() {
+                    Ok(message) => Some(Ok(message)),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+                })
+                .expect("Waiting with no timeout only ends with a message"),
+        }
+    }
+
+    pub fn recv_timeout<T>(
+        &self,
+        rx: &Receiver<T>,
+        timeout: Duration,
+    ) -> Result<T, RecvTimeoutError> {
+        match &self.kind {
+            Kind::Real(_) => rx.recv_timeout(timeout),
+            Kind::Virtual(scheduler) => scheduler
+                .wait(Some(timeout), || match rx.try_recv() {
+                    Ok(message) => Some(Ok(message)),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => {
+                        Some(Err(RecvTimeoutError::Disconnected))
+                    }
+                })
+                .unwrap_or(Err(RecvTimeoutError::Timeout)),
+        }
+    }
+
+    /// Lock `mutex` and wait on `condvar` while `condition` holds, for at
+    /// most `timeout`, like [`Condvar::wait_timeout_while`].
+    pub fn wait_timeout_while<'a, T, F>(
+        &self,
+        mutex: &'a Mutex<T>,
+        condvar: &Condvar,
+        timeout: Duration,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        match &self.kind {
+            Kind::Real(_) => {
+                let guard = mutex.lock().unwrap();
+                condvar
+                    .wait_timeout_while(guard, timeout, condition)
+                    .unwrap()
+                    .0
+            }
+            Kind::Virtual(scheduler) => scheduler
+                .wait(Some(timeout), || {
+                    let mut guard = mutex.lock().unwrap();
+                    (!condition(&mut guard)).then_some(guard)
+                })
+                .unwrap_or_else(|| mutex.lock().unwrap()),
+        }
+    }
+
+    /// Start a thread that takes part in the clock's schedule.
+    pub fn spawn<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let ticket = self.ticket();
+        let finished = Arc::new(AtomicBool::new(false));
+        let thread_finished = finished.clone();
+        let inner = thread::spawn(move || {
+            let _turn = ticket.map(|ticket| ticket.start(thread_finished));
+            f()
+        });
+        JoinHandle {
+            inner,
+            finished,
+            clock: self.clone(),
+        }
+    }
+
+    /// Like [`Clock::spawn`], but for a thread in `scope`.
+    pub fn spawn_scoped<'scope, F, T>(
+        &self,
+        scope: &'scope Scope<'scope, '_>,
+        f: F,
+    ) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let ticket = self.ticket();
+        scope.spawn(move || {
+            let finished = Arc::new(AtomicBool::new(false));
+            let _turn = ticket.map(|ticket| ticket.start(finished));
+            f()
+        })
+    }
+
+    /// Take the current thread out of the schedule, so it can block on
+    /// things the clock doesn't know about (like waiting for a scope to
+    /// end) without holding everyone else up. Does nothing in real time.
+    pub fn leave(&self) {
+        if let Kind::Virtual(scheduler) = &self.kind {
+            scheduler.leave();
+        }
+    }
+
+    /// Note that the current thread has done something other threads might
+    /// be waiting for, like sending them a message.
+    pub(crate) fn changed(&self) {
+        if let Kind::Virtual(scheduler) = &self.kind {
+            scheduler.changed();
+        }
+    }
+
+    /// A place in the schedule for a new thread, worked out before the
+    /// thread starts so it doesn't depend on when the thread gets going.
+    fn ticket(&self) -> Option<Ticket> {
+        match &self.kind {
+            Kind::Real(_) => None,
+            Kind::Virtual(scheduler) => Some(Ticket {
+                scheduler: scheduler.clone(),
+                participant: scheduler.register(),
+            }),
+        }
+    }
+}
+
+/// A thread started by [`Clock::spawn`].
+pub struct JoinHandle<T> {
+    inner: thread::JoinHandle<T>,
+    finished: Arc<AtomicBool>,
+    clock: Clock,
+}
+
+impl<T> JoinHandle<T> {
+    /// Wait for the thread to finish, like [`thread::JoinHandle::join`].
+    pub fn join(self) -> thread::Result<T> {
+        if let Kind::Virtual(scheduler) = &self.clock.kind {
+            let finished = &self.finished;
+            scheduler
+                .wait(None, || finished.load(Ordering::Acquire).then_some(()));
+        }
+        self.inner.join()
+    }
+}
+
+struct Ticket {
+    scheduler: Arc<Scheduler>,
+    participant: usize,
+}
+
+impl Ticket {
+    /// Wait for the new thread's first turn.
+    fn start(self, finished: Arc<AtomicBool>) -> Turn {
+        PARTICIPANT.with(|p| p.set(Some(self.participant)));
+        self.scheduler.wait_for_turn(self.participant);
+        Turn {
+            scheduler: self.scheduler,
+            finished,
+        }
+    }
+}
+
+/// Takes a spawned thread out of the schedule when it finishes, even if it
+/// panics.
+struct Turn {
+    scheduler: Arc<Scheduler>,
+    finished: Arc<AtomicBool>,
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.finished.store(true, Ordering::Release);
+        self.scheduler.leave();
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Status {
+    /// Has the turn.
+    Running,
+    /// Can run as soon as it's picked.
+    Ready,
+    /// Asleep until this time.
+    Sleeping(Duration),
+    /// Waiting for another thread to change something, or until the
+    /// deadline if there is one.
+    Waiting(Option<Duration>),
+    Finished,
+}
+
+#[derive(Debug)]
+struct Participant {
+    status: Status,
+    /// Whether the thread has done something during its turn that another
+    /// thread could be waiting for.
+    changed: bool,
+    /// Signalled when the thread is given the turn.
+    wakeup: Arc<Condvar>,
+}
+
+#[derive(Debug)]
+struct Scheduler {
+    schedule: Mutex<Schedule>,
+}
+
+#[derive(Debug)]
+struct Schedule {
+    now: Duration,
+    running: Option<usize>,
+    participants: Vec<Participant>,
+}
+
+impl Scheduler {
+    /// A schedule with the current thread in it, holding the turn.
+    fn new() -> Self {
+        PARTICIPANT.with(|p| p.set(Some(0)));
+        Self {
+            schedule: Mutex::new(Schedule {
+                now: Duration::ZERO,
+                running: Some(0),
+                participants: vec![Participant {
+                    status: Status::Running,
+                    changed: true,
+                    wakeup: Arc::new(Condvar::new()),
+                }],
+            }),
+        }
+    }
+
+    fn current() -> usize {
+        PARTICIPANT
+            .with(|p| p.get())
+            .expect("Only threads started by a virtual clock can wait on it")
+    }
+
+    fn now(&self) -> Duration {
+        self.schedule.lock().unwrap().now
+    }
+
+    fn register(&self) -> usize {
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.participants.push(Participant {
+            status: Status::Ready,
+            changed: true,
+            wakeup: Arc::new(Condvar::new()),
+        });
+        schedule.participants.len() - 1
+    }
+
+    fn changed(&self) {
+        let me = Self::current();
+        self.schedule.lock().unwrap().participants[me].changed = true;
+    }
+
+    fn wait_for_turn(&self, me: usize) {
+        let schedule = self.schedule.lock().unwrap();
+        let wakeup = schedule.participants[me].wakeup.clone();
+        let _schedule = wakeup
+            .wait_while(schedule, |schedule| schedule.running != Some(me))
+            .unwrap();
+    }
+
+    /// Give up the turn with the given status and wait to get it back.
+    fn park(&self, status: Status) {
+        let me = Self::current();
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.participants[me].status = status;
+        schedule.hand_over(me);
+        let wakeup = schedule.participants[me].wakeup.clone();
+        let mut schedule = wakeup
+            .wait_while(schedule, |schedule| schedule.running != Some(me))
+            .unwrap();
+        // Waking up from a sleep means getting on with something, but a
+        // waiting thread has only been woken up to check again.
+        schedule.participants[me].changed =
+            matches!(status, Status::Sleeping(_));
+    }
+
+    /// Keep calling `attempt` until it returns something, waiting for other
+    /// threads in between. Gives up with `None` after `timeout`.
+    fn wait<R>(
+        &self,
+        timeout: Option<Duration>,
+        mut attempt: impl FnMut() -> Option<R>,
+    ) -> Option<R> {
+        let deadline = timeout.map(|timeout| self.now() + timeout);
+        loop {
+            if let Some(result) = attempt() {
+                self.changed();
+                return Some(result);
+            }
+            if deadline.is_some_and(|deadline| self.now() >= deadline) {
+                return None;
+            }
+            self.park(Status::Waiting(deadline));
+        }
+    }
+
+    fn leave(&self) {
+        let me = Self::current();
+        let mut schedule = self.schedule.lock().unwrap();
+        schedule.participants[me].status = Status::Finished;
+        schedule.participants[me].changed = true;
+        schedule.hand_over(me);
+        PARTICIPANT.with(|p| p.set(None));
+    }
+}
+
+impl Schedule {
+    /// Pass the turn on from `from`. Anyone waiting gets to check again if
+    /// `from` changed anything, otherwise the turn goes to whoever is due
+    /// next, moving the time on to then. Ties go to whoever joined the
+    /// schedule first. If everyone left is waiting with no deadline nobody
+    /// gets the turn, just like a real deadlock.
+    fn hand_over(&mut self, from: usize) {
+        if self.participants[from].changed {
+            for (i, participant) in self.participants.iter_mut().enumerate() {
+                if i != from && matches!(participant.status, Status::Waiting(_))
+                {
+                    participant.status = Status::Ready;
+                }
+            }
+        }
+
+        let ready = self
+            .participants
+            .iter()
+            .position(|participant| participant.status == Status::Ready);
+        let next = ready.or_else(|| {
+            self.participants
+                .iter()
+                .enumerate()
+                .filter_map(|(i, participant)| match participant.status {
+                    Status::Sleeping(at) | Status::Waiting(Some(at)) => {
+                        Some((at, i))
+                    }
+                    _ => None,
+                })
+                .min()
+                .map(|(at, i)| {
+                    self.now = self.now.max(at);
+                    i
+                })
+        });
+
+        self.running = next;
+        if let Some(next) = next {
+            let participant = &mut self.participants[next];
+            participant.status = Status::Running;
+            participant.wakeup.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{harness, Registry, SimConfig};
+
+    #[test]
+    fn virtual_sleeps_take_no_time_and_run_in_order() {
+        let clock = Clock::simulated();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let started = Instant::now();
+        let handles: Vec<_> = [30, 10, 20]
+            .into_iter()
+            .map(|millis| {
+                let clock = clock.clone();
+                let tx = tx.clone();
+                clock.clone().spawn(move || {
+                    clock.sleep(Duration::from_secs(millis));
+                    tx.send((millis, clock.now())).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        clock.leave();
+        drop(tx);
+
+        let woken: Vec<_> = rx.iter().collect();
+        let secs = Duration::from_secs;
+        assert_eq!(woken, [(10, secs(10)), (20, secs(20)), (30, secs(30))]);
+        assert!(started.elapsed() < secs(5));
+    }
+
+    #[test]
+    fn fixed_timing_runs_are_identical_in_virtual_time() {
+        let config = SimConfig {
+            run_time_seconds: 1,
+            virtual_time: true,
+            ..SimConfig::default()
+        };
+        for strategy in Registry::builtin().iter() {
+            let (first, first_events) =
+                harness::run_recorded(strategy, &config);
+            let (second, second_events) =
+                harness::run_recorded(strategy, &config);
+            assert_eq!(
+                first_events,
+                second_events,
+                "{} didn't do the same thing twice",
+                strategy.name()
+            );
+            assert_eq!(first.meals, second.meals);
+            assert!(!first_events.is_empty());
+        }
+    }
+
+    #[test]
+    fn seeded_random_runs_are_identical_in_virtual_time() {
+        let config = SimConfig {
+            run_time_seconds: 1,
+            random: true,
+            virtual_time: true,
+            seed: Some(42),
+            ..SimConfig::default()
+        };
+        for strategy in Registry::builtin().iter() {
+            let (first, first_events) =
+                harness::run_recorded(strategy, &config);
+            let (second, second_events) =
+                harness::run_recorded(strategy, &config);
+            assert_eq!(
+                first_events,
+                second_events,
+                "{} didn't do the same thing twice with the same seed",
+                strategy.name()
+            );
+            assert_eq!(first.meals, second.meals);
+            assert_eq!(first.seed, 42);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Counting how often philosophers try to pick up forks and fail, and
+//! spotting stretches of a run where they keep trying but nobody eats.
+//!
+//! Strategies that poll for their forks (like `two_forks` and `sequential`)
+//! never block, so they can't deadlock, but they can burn through a lot of
+//! attempts for every meal or, at worst, livelock: everyone busy trying and
+//! nobody getting anywhere.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a stretch of a run has to go without a meal, while attempts keep
+/// failing, to count as spinning.
+pub const SPIN_WINDOW: Duration = Duration::from_millis(50);
+
+/// Failed attempts so far, by philosopher (indexed by `id - 1`) and by fork.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AttemptCounts {
+    pub by_philosopher: Vec<u64>,
+    /// How often each fork was the one that was already taken.
+    pub by_fork: Vec<u64>,
+}
+
+impl AttemptCounts {
+    pub fn total(&self) -> u64 {
+        self.by_philosopher.iter().sum()
+    }
+}
+
+/// Where failed attempts get counted, shared between the forks and the
+/// harness. Every philosopher and fork has a counter of their own, so
+/// philosophers failing at the same time don't hold each other up.
+#[derive(Clone, Debug)]
+pub struct FailedAttempts {
+    by_philosopher: Arc<[AtomicU64]>,
+    by_fork: Arc<[AtomicU64]>,
+}
+
+impl FailedAttempts {
+    /// Counters for a table of `n_philosophers`.
+    pub fn new(n_philosophers: usize) -> Self {
+        let counters = || (0..n_philosophers).map(|_| AtomicU64::new(0));
+        Self {
+            by_philosopher: counters().collect(),
+            by_fork: counters().collect(),
+        }
+    }
+
+    /// Philosopher `id` tried to pick up `fork` but it wasn't free.
+    pub fn record(&self, id: usize, fork: usize) {
+        self.by_philosopher[id - 1].fetch_add(1, Ordering::Relaxed);
+        self.by_fork[fork].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> AttemptCounts {
+        AttemptCounts {
+            by_philosopher: load(&self.by_philosopher),
+            by_fork: load(&self.by_fork),
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.by_philosopher
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+fn load(counters: &[AtomicU64]) -> Vec<u64> {
+    counters
+        .iter()
+        .map(|count| count.load(Ordering::Relaxed))
+        .collect()
+}
+
+/// A stretch of a run where attempts kept failing and nobody ate.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SpinPeriod {
+    pub start: Duration,
+    pub end: Duration,
+    /// Failed attempts during the period.
+    pub attempts: u64,
+}
+
+impl SpinPeriod {
+    pub fn length(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Splits a run into windows of at least [`SPIN_WINDOW`] and remembers the
+/// ones with failed attempts but no meals, joining neighbouring ones up.
+#[derive(Clone, Debug, Default)]
+pub struct SpinWatch {
+    window_start: Duration,
+    attempts_at_start: u64,
+    meals: u64,
+    periods: Vec<SpinPeriod>,
+}
+
+impl SpinWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Somebody finished a meal.
+    pub fn meal(&mut self) {
+        self.meals += 1;
+    }
+
+    /// Bring the watch up to `now`, when there had been `attempts` failed
+    /// attempts in total.
+    pub fn update(&mut self, now: Duration, attempts: u64) {
+        if now.saturating_sub(self.window_start) >= SPIN_WINDOW {
+            self.close_window(now, attempts);
+        }
+    }
+
+    /// The spinning periods, closing the last window at `now` if it's
+    /// already been spinning for long enough.
+    pub fn finish(mut self, now: Duration, attempts: u64) -> Vec<SpinPeriod> {
+        self.update(now, attempts);
+        self.periods
+    }
+
+    fn close_window(&mut self, now: Duration, attempts: u64) {
+        let failed = attempts.saturating_sub(self.attempts_at_start);
+        if self.meals == 0 && failed > 0 {
+            match self.periods.last_mut() {
+                Some(last) if last.end == self.window_start => {
+                    last.end = now;
+                    last.attempts += failed;
+                }
+                _ => self.periods.push(SpinPeriod {
+                    start: self.window_start,
+                    end: now,
+                    attempts: failed,
+                }),
+            }
+        }
+        self.window_start = now;
+        self.attempts_at_start = attempts;
+        self.meals = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn attempts_are_counted_by_philosopher_and_fork() {
+        let attempts = FailedAttempts::new(3);
+        attempts.record(2, 1);
+        attempts.record(2, 1);
+        attempts.record(2, 2);
+        attempts.record(1, 0);
+        let counts = attempts.counts();
+        assert_eq!(counts.by_philosopher, [1, 3, 0]);
+        assert_eq!(counts.by_fork, [1, 2, 1]);
+        assert_eq!(attempts.total(), 4);
+    }
+
+    #[test]
+    fn windows_with_attempts_but_no_meals_are_flagged() {
+        let mut watch = SpinWatch::new();
+        // A meal in the first window, so it's fine however many attempts.
+        watch.update(ms(10), 100);
+        watch.meal();
+        watch.update(ms(50), 200);
+        // Two windows of attempts and no meals, which join up.
+        watch.update(ms(100), 300);
+        watch.update(ms(160), 350);
+        // No attempts and no meals is just everyone thinking.
+        watch.update(ms(210), 350);
+        // A short last window doesn't count.
+        let periods = watch.finish(ms(220), 400);
+        assert_eq!(
+            periods,
+            [SpinPeriod {
+                start: ms(50),
+                end: ms(160),
+                attempts: 150,
+            }]
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Spotting deadlocks while a strategy runs, rather than waiting for somebody
+//! to starve.
+//!
+//! [`Forks`] are forks that know who's holding each of them and who's
+//! waiting for them, which makes a wait-for graph: a philosopher waiting for
+//! a fork points at whoever has it. Each philosopher waits for one fork at a
+//! time, so the graph is easy to follow, and if following it from somebody
+//! who has just started waiting leads back to them, nobody in that cycle can
+//! ever move again. That gets reported to the harness through the
+//! [`EventSink`], which ends the run.
+
+use crate::{Clock, EventKind, EventSink};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a philosopher waiting for a fork checks whether the kill switch
+/// has been activated. Putting a fork down wakes whoever is waiting for it
+/// straight away, so this is only how long giving up can take.
+const KILL_SWITCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A cycle in the wait-for graph: each philosopher is waiting for the fork at
+/// the same index, which the next philosopher (wrapping round) is holding.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Deadlock {
+    pub philosophers: Vec<usize>,
+    pub forks: Vec<usize>,
+}
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let n = self.philosophers.len();
+        for (i, (id, fork)) in
+            self.philosophers.iter().zip(&self.forks).enumerate()
+        {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            let holder = self.philosophers[(i + 1) % n];
+            write!(
+                f,
+                "philosopher {id} is waiting for fork {fork} held by \
+                 philosopher {holder}"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a deadlock gets reported, shared between everything that might
+/// spot one and the harness. Only the first one is kept.
+#[derive(Clone, Debug, Default)]
+pub struct DeadlockAlarm {
+    deadlock: Arc<Mutex<Option<Deadlock>>>,
+}
+
+impl DeadlockAlarm {
+    pub fn raise(&self, deadlock: Deadlock) {
+        self.deadlock.lock().unwrap().get_or_insert(deadlock);
+    }
+
+    /// The deadlock, if there's been one.
+    pub fn raised(&self) -> Option<Deadlock> {
+        self.deadlock.lock().unwrap().clone()
+    }
+}
+
+/// The wait-for graph's edges: who's waiting for each fork, indexed by fork
+/// number. Who's holding each fork comes from the forks themselves.
+#[derive(Debug)]
+struct WaitFor {
+    waiters: Vec<Vec<usize>>,
+}
+
+impl WaitFor {
+    fn waiting_for(&self, id: usize) -> Option<usize> {
+        self.waiters
+            .iter()
+            .position(|waiters| waiters.contains(&id))
+    }
+
+    fn stop_waiting(&mut self, id: usize, fork: usize) {
+        self.waiters[fork].retain(|&waiter| waiter != id);
+    }
+
+    /// The cycle `id` is in, if there is one, with `holder` saying who's
+    /// holding each fork.
+    fn cycle(
+        &self,
+        id: usize,
+        holder: impl Fn(usize) -> Option<usize>,
+    ) -> Option<Deadlock> {
+        let mut philosophers = vec![];
+        let mut forks = vec![];
+        let mut current = id;
+        loop {
+            let fork = self.waiting_for(current)?;
+            let holder = holder(fork)?;
+            philosophers.push(current);
+            forks.push(fork);
+            if holder == id {
+                return Some(Deadlock {
+                    philosophers,
+                    forks,
+                });
+            }
+            if philosophers.contains(&holder) {
+                // A cycle `id` is only waiting on, which was reported when
+                // it closed.
+                return None;
+            }
+            current = holder;
+        }
+    }
+}
+
+/// A single fork: who's holding it, and somewhere to wait for them to put it
+/// down.
+#[derive(Debug, Default)]
+struct Fork {
+    holder: Mutex<Option<usize>>,
+    put_down: Condvar,
+}
+
+/// A table's worth of forks that keep track of who has them, see the
+/// [module docs](self). Picking a fork up and putting it down is reported to
+/// the sink, so philosophers don't have to, and so are failed attempts to
+/// pick forks up (see [`contention`](crate::contention)).
+///
+/// Every fork has a lock of its own, so philosophers only ever wait for
+/// their neighbours. The wait-for graph is kept alongside, and is only
+/// looked at when somebody starts waiting.
+#[derive(Debug)]
+pub struct Forks {
+    forks: Vec<Fork>,
+    graph: Mutex<WaitFor>,
+    clock: Clock,
+    sink: EventSink,
+}
+
+impl Forks {
+    pub fn new(n_forks: usize, clock: Clock, sink: EventSink) -> Self {
+        Self {
+            forks: (0..n_forks).map(|_| Fork::default()).collect(),
+            graph: Mutex::new(WaitFor {
+                waiters: vec![vec![]; n_forks],
+            }),
+            clock,
+            sink,
+        }
+    }
+
+    /// Pick up `fork` for philosopher `id`, waiting as long as it takes. Gives
+    /// up and returns `None` if the kill switch is activated first, which is
+    /// the only way out of a deadlock. The fork is put down again when the
+    /// guard is dropped.
+    pub fn pick_up(
+        &self,
+        id: usize,
+        fork: usize,
+        kill_switch: &AtomicBool,
+    ) -> Option<ForkGuard<'_>> {
+        if let Some(guard) = self.take_if_free(id, fork) {
+            return Some(guard);
+        }
+
+        // Starting to wait is the only thing that can close a cycle. Anyone
+        // else who's waiting can't put a fork down without going through the
+        // graph first, so the holders stay put while it's followed.
+        {
+            let mut graph = self.graph.lock().unwrap();
+            graph.waiters[fork].push(id);
+            if let Some(deadlock) = graph.cycle(id, |fork| self.holder(fork)) {
+                log::debug!("Deadlock: {deadlock}");
+                self.sink.report_deadlock(deadlock);
+            }
+        }
+
+        let taken = loop {
+            let mut holder = self.clock.wait_timeout_while(
+                &self.forks[fork].holder,
+                &self.forks[fork].put_down,
+                KILL_SWITCH_INTERVAL,
+                |holder| holder.is_some(),
+            );
+            if holder.is_none() {
+                *holder = Some(id);
+                break true;
+            }
+            if kill_switch.load(Ordering::Relaxed) {
+                break false;
+            }
+        };
+        self.graph.lock().unwrap().stop_waiting(id, fork);
+        taken.then(|| self.picked_up(id, fork))
+    }
+
+    /// Pick up `fork` for philosopher `id` if it's free, without waiting for
+    /// it. Finding it taken counts as a failed attempt.
+    pub fn try_pick_up(&self, id: usize, fork: usize) -> Option<ForkGuard<'_>> {
+        let guard = self.take_if_free(id, fork);
+        if guard.is_none() {
+            self.sink.failed_attempt(id, fork);
+        }
+        guard
+    }
+
+    fn take_if_free(&self, id: usize, fork: usize) -> Option<ForkGuard<'_>> {
+        let mut holder = self.forks[fork].holder.lock().unwrap();
+        if holder.is_some() {
+            return None;
+        }
+        *holder = Some(id);
+        drop(holder);
+        Some(self.picked_up(id, fork))
+    }
+
+    fn picked_up(&self, id: usize, fork: usize) -> ForkGuard<'_> {
+        // Nobody waits for a fork to be picked up, only for it to be put
+        // down, so this doesn't wake anyone on a virtual clock.
+        self.sink.emit_quietly(id, EventKind::AcquiredFork(fork));
+        ForkGuard {
+            forks: self,
+            id,
+            fork,
+            used: true,
+        }
+    }
+
+    /// Who's holding `fork`.
+    pub fn holder(&self, fork: usize) -> Option<usize> {
+        *self.forks[fork].holder.lock().unwrap()
+    }
+
+    /// Who's waiting for `fork`, in the order they started waiting.
+    pub fn waiters(&self, fork: usize) -> Vec<usize> {
+        self.graph.lock().unwrap().waiters[fork].clone()
+    }
+}
+
+/// A fork that's been picked up, which is put down when this is dropped.
+#[derive(Debug)]
+pub struct ForkGuard<'a> {
+    forks: &'a Forks,
+    id: usize,
+    fork: usize,
+    /// Whether anyone could have seen the fork being held.
+    used: bool,
+}
+
+impl ForkGuard<'_> {
+    pub fn fork(&self) -> usize {
+        self.fork
+    }
+
+    /// Put the fork straight back down, in the same go as it was picked up,
+    /// because the other fork wasn't free. On a virtual clock nobody else
+    /// has had a turn in between, so nothing has changed for anyone waiting
+    /// and they aren't woken up to check again.
+    pub fn put_back(mut self) {
+        self.used = false;
+    }
+}
+
+impl Drop for ForkGuard<'_> {
+    fn drop(&mut self) {
+        let forks = self.forks;
+        let released = EventKind::ReleasedFork(self.fork);
+        if self.used {
+            forks.sink.emit(self.id, released);
+        } else {
+            forks.sink.emit_quietly(self.id, released);
+        }
+        let fork = &forks.forks[self.fork];
+        *fork.holder.lock().unwrap() = None;
+        fork.put_down.notify_one();
+        if self.used {
+            forks.clock.changed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{self, Outcome};
+    use crate::{Properties, SimConfig, Strategy};
+    use std::sync::Barrier;
+
+    /// Everyone picks up their left fork and then, once everyone has one,
+    /// their right fork.
+    struct LeftFirst;
+
+    impl Strategy for LeftFirst {
+        fn name(&self) -> &'static str {
+            "left_first"
+        }
+
+        fn description(&self) -> &'static str {
+            "Deadlocks straight away"
+        }
+
+        fn properties(&self) -> Properties {
+            Properties {
+                deadlock_free: false,
+                starvation_free: false,
+                centralised: false,
+            }
+        }
+
+        fn run(
+            &self,
+            config: &SimConfig,
+            clock: Clock,
+            sink: EventSink,
+            cancel: Arc<AtomicBool>,
+        ) {
+            let n = config.n_philosophers;
+            let forks = Forks::new(n, clock.clone(), sink);
+            let barrier = Barrier::new(n);
+            std::thread::scope(|scope| {
+                for id in 1..n + 1 {
+                    let (forks, barrier, cancel) = (&forks, &barrier, &cancel);
+                    scope.spawn(move || {
+                        let _left = forks.pick_up(id, id - 1, cancel);
+                        barrier.wait();
+                        let _right = forks.pick_up(id, id % n, cancel);
+                    });
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn a_wait_for_cycle_ends_the_run() {
+        let config = SimConfig {
+            n_philosophers: 3,
+            hunger_threshold_millis: 60_000,
+            ..SimConfig::default()
+        };
+        let (report, events) = harness::run_recorded(&LeftFirst, &config);
+        let Outcome::Deadlocked(deadlock) = &report.outcome else {
+            panic!("expected a deadlock, got {:?}", report.outcome);
+        };
+        assert!(report.elapsed < Duration::from_secs(5));
+
+        // Nothing in the events shows the deadlock, but it's saved with them.
+        let replayed = harness::replay(&config, &events, &report.end(), |_| {});
+        assert_eq!(replayed.outcome, report.outcome);
+        assert_eq!(replayed.elapsed, report.elapsed);
+
+        // The cycle starts with whoever closed it, so line it up from
+        // philosopher 1.
+        let mut philosophers = deadlock.philosophers.clone();
+        let first = philosophers.iter().position(|&id| id == 1).unwrap();
+        philosophers.rotate_left(first);
+        assert_eq!(philosophers, [1, 2, 3]);
+        for (id, fork) in deadlock.philosophers.iter().zip(&deadlock.forks) {
+            assert_eq!(*fork, id % 3, "philosopher {id} waits to their right");
+        }
+    }
+
+    #[test]
+    fn forks_know_their_holder_and_waiters() {
+        let clock = Clock::real();
+        let (sink, _rx) = EventSink::channel(clock.clone(), 2);
+        let forks = Forks::new(2, clock, sink.clone());
+        let kill_switch = AtomicBool::new(false);
+
+        let guard = forks.pick_up(1, 0, &kill_switch).unwrap();
+        assert_eq!(forks.holder(0), Some(1));
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                forks.pick_up(2, 0, &kill_switch).map(|guard| guard.fork())
+            });
+            while forks.waiters(0).is_empty() {
+                std::thread::yield_now();
+            }
+            assert_eq!(forks.waiters(0), [2]);
+            drop(guard);
+            assert_eq!(waiter.join().unwrap(), Some(0));
+        });
+        assert_eq!(forks.holder(0), None);
+        assert!(forks.waiters(0).is_empty());
+        assert_eq!(sink.deadlock_alarm().raised(), None);
+    }
+}
@@ -0,0 +1,530 @@
+//! How long philosophers think and eat for when timing is random.
+//!
+//! Every distribution is written the same way on the command line and in
+//! traces, as a name and its parameters in milliseconds:
+//!
+//! ```text
+//! fixed:10                 always 10ms
+//! uniform:1,10             anywhere from 1ms up to 10ms
+//! exp:5                    exponential with a mean of 5ms
+//! normal:10,3              normal, cut off at 0ms
+//! normal:10,3,5,15         normal, cut off at 5ms and 15ms
+//! lognormal:5,1            log-normal with a median of 5ms and a sigma of 1
+//! bimodal:2,0.5,40,5,0.1   normal(2, 0.5) most of the time, but normal(40, 5)
+//!                          one time in ten
+//! file:durations.txt       one of the durations in the file, picked at random
+//! ```
+//!
+//! A file has a duration in milliseconds on each line. Blank lines and lines
+//! starting with `#` are skipped.
+
+use rand::Rng;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Nobody thinks or eats for longer than this, however far out in the tail a
+/// sample lands, so a freak sample can't overflow the clock.
+pub const LONGEST: Duration = Duration::from_secs(60 * 60);
+
+/// Truncated normals are sampled again until they land in range, up to this
+/// many times before settling for the nearest end of the range.
+const MAX_REJECTIONS: usize = 100;
+
+/// What a philosopher spends a duration doing.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Activity {
+    Thinking,
+    Eating,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DurationDistribution {
+    Fixed(Duration),
+    /// From `min` up to but not including `max`.
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    Exponential {
+        mean: Duration,
+    },
+    /// A normal distribution with anything outside `min..=max` thrown away.
+    Normal {
+        mean: Duration,
+        std_dev: Duration,
+        min: Duration,
+        max: Option<Duration>,
+    },
+    /// `exp(N(ln(median), sigma))`, the larger `sigma` the heavier the tail.
+    LogNormal {
+        median: Duration,
+        sigma: f64,
+    },
+    /// Two normals cut off at zero, the second picked with probability
+    /// `p_second`.
+    Bimodal {
+        first_mean: Duration,
+        first_std_dev: Duration,
+        second_mean: Duration,
+        second_std_dev: Duration,
+        p_second: f64,
+    },
+    /// Samples picked uniformly from a file of measured durations.
+    Empirical {
+        path: PathBuf,
+        samples: Arc<[Duration]>,
+    },
+}
+
+impl DurationDistribution {
+    /// Parse a distribution written as in the [module docs](self). Files are
+    /// read straight away.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        if name == "file" {
+            return Self::from_file(PathBuf::from(params));
+        }
+        let params = params
+            .split(',')
+            .map(|param| param.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                format!("Expected numbers after '{name}:', got '{s}'")
+            })?;
+        let millis = |i: usize| millis(params[i], s);
+        let expect = |counts: &[usize]| {
+            if counts.contains(&params.len()) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "'{name}' takes {} parameters, got '{s}'",
+                    counts
+                        .iter()
+                        .map(|count| count.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                ))
+            }
+        };
+
+        let distribution = match name {
+            "fixed" => {
+                expect(&[1])?;
+                Self::Fixed(millis(0)?)
+            }
+            "uniform" => {
+                expect(&[2])?;
+                Self::Uniform {
+                    min: millis(0)?,
+                    max: millis(1)?,
+                }
+            }
+            "exp" => {
+                expect(&[1])?;
+                Self::Exponential { mean: millis(0)? }
+            }
+            "normal" => {
+                expect(&[2, 4])?;
+                Self::Normal {
+                    mean: millis(0)?,
+                    std_dev: millis(1)?,
+                    min: if params.len() == 4 {
+                        millis(2)?
+                    } else {
+                        Duration::ZERO
+                    },
+                    max: if params.len() == 4 {
+                        Some(millis(3)?)
+                    } else {
+                        None
+                    },
+                }
+            }
+            "lognormal" => {
+                expect(&[2])?;
+                Self::LogNormal {
+                    median: millis(0)?,
+                    sigma: params[1],
+                }
+            }
+            "bimodal" => {
+                expect(&[5])?;
+                Self::Bimodal {
+                    first_mean: millis(0)?,
+                    first_std_dev: millis(1)?,
+                    second_mean: millis(2)?,
+                    second_std_dev: millis(3)?,
+                    p_second: params[4],
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown distribution '{name}', expected fixed, uniform, \
+                     exp, normal, lognormal, bimodal or file"
+                ))
+            }
+        };
+        distribution
+            .validate()
+            .map_err(|e| format!("{e} in '{s}'"))?;
+        Ok(distribution)
+    }
+
+    /// Read an empirical distribution from a file of durations.
+    pub fn from_file(path: PathBuf) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            format!("Couldn't read durations from {}: {e}", path.display())
+        })?;
+        let samples = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse()
+                    .map_err(|_| {
+                        format!(
+                            "Expected a number of milliseconds in {}, got \
+                             '{line}'",
+                            path.display()
+                        )
+                    })
+                    .and_then(|value| millis(value, line))
+            })
+            .collect::<Result<Arc<[Duration]>, _>>()?;
+        if samples.is_empty() {
+            return Err(format!(
+                "There are no durations in {}",
+                path.display()
+            ));
+        }
+        Ok(Self::Empirical { path, samples })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match *self {
+            Self::Uniform { min, max } if min >= max => {
+                Err("The minimum must be less than the maximum".to_string())
+            }
+            Self::Exponential { mean } if mean.is_zero() => {
+                Err("The mean must be more than zero".to_string())
+            }
+            Self::Normal { min, max, .. }
+                if max.is_some_and(|max| min > max) =>
+            {
+                Err("The minimum can't be more than the maximum".to_string())
+            }
+            Self::LogNormal { median, .. } if median.is_zero() => {
+                Err("The median must be more than zero".to_string())
+            }
+            Self::LogNormal { sigma, .. } if sigma.is_nan() || sigma < 0.0 => {
+                Err("Sigma can't be negative".to_string())
+            }
+            Self::Bimodal { p_second, .. }
+                if !(0.0..=1.0).contains(&p_second) =>
+            {
+                Err("The probability must be between 0 and 1".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Draw a duration, never longer than [`LONGEST`].
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Duration {
+        let millis = match self {
+            Self::Fixed(duration) => return *duration,
+            Self::Uniform { min, max } => return rng.gen_range(*min..*max),
+            Self::Exponential { mean } => {
+                // `1 - u` is never zero, so the log is always finite.
+                -as_millis(*mean) * (1.0 - rng.gen::<f64>()).ln()
+            }
+            Self::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => truncated_normal(
+                rng,
+                as_millis(*mean),
+                as_millis(*std_dev),
+                as_millis(*min),
+                max.map_or(f64::INFINITY, as_millis),
+            ),
+            Self::LogNormal { median, sigma } => {
+                (as_millis(*median).ln() + sigma * standard_normal(rng)).exp()
+            }
+            Self::Bimodal {
+                first_mean,
+                first_std_dev,
+                second_mean,
+                second_std_dev,
+                p_second,
+            } => {
+                let (mean, std_dev) = if rng.gen_bool(*p_second) {
+                    (second_mean, second_std_dev)
+                } else {
+                    (first_mean, first_std_dev)
+                };
+                truncated_normal(
+                    rng,
+                    as_millis(*mean),
+                    as_millis(*std_dev),
+                    0.0,
+                    f64::INFINITY,
+                )
+            }
+            Self::Empirical { samples, .. } => {
+                return samples[rng.gen_range(0..samples.len())]
+            }
+        };
+        Duration::from_secs_f64(
+            (millis / 1000.0).clamp(0.0, LONGEST.as_secs_f64()),
+        )
+    }
+}
+
+impl fmt::Display for DurationDistribution {
+    /// Written the same way [`DurationDistribution::parse`] reads it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: &Duration| as_millis(*duration);
+        match self {
+            Self::Fixed(duration) => write!(f, "fixed:{}", ms(duration)),
+            Self::Uniform { min, max } => {
+                write!(f, "uniform:{},{}", ms(min), ms(max))
+            }
+            Self::Exponential { mean } => write!(f, "exp:{}", ms(mean)),
+            Self::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                write!(f, "normal:{},{}", ms(mean), ms(std_dev))?;
+                match max {
+                    Some(max) => write!(f, ",{},{}", ms(min), ms(max)),
+                    None => Ok(()),
+                }
+            }
+            Self::LogNormal { median, sigma } => {
+                write!(f, "lognormal:{},{sigma}", ms(median))
+            }
+            Self::Bimodal {
+                first_mean,
+                first_std_dev,
+                second_mean,
+                second_std_dev,
+                p_second,
+            } => write!(
+                f,
+                "bimodal:{},{},{},{},{p_second}",
+                ms(first_mean),
+                ms(first_std_dev),
+                ms(second_mean),
+                ms(second_std_dev)
+            ),
+            Self::Empirical { path, .. } => {
+                write!(f, "file:{}", path.display())
+            }
+        }
+    }
+}
+
+/// A distribution for one activity, for everyone or for one philosopher.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DurationRule {
+    pub activity: Activity,
+    /// Only applies to this philosopher, or to everyone if `None`.
+    pub philosopher: Option<usize>,
+    pub distribution: DurationDistribution,
+}
+
+impl DurationRule {
+    /// Parse `DIST` or `ID=DIST`, see [`DurationDistribution::parse`].
+    pub fn parse(activity: Activity, s: &str) -> Result<Self, String> {
+        let (philosopher, distribution) = match s.split_once('=') {
+            Some((id, distribution)) => {
+                let id = id.trim().parse().map_err(|_| {
+                    format!(
+                        "Expected a philosopher's number before '=', got '{s}'"
+                    )
+                })?;
+                (Some(id), distribution)
+            }
+            None => (None, s),
+        };
+        Ok(Self {
+            activity,
+            philosopher,
+            distribution: DurationDistribution::parse(distribution)?,
+        })
+    }
+}
+
+impl fmt::Display for DurationRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = self.philosopher {
+            write!(f, "{id}=")?;
+        }
+        write!(f, "{}", self.distribution)
+    }
+}
+
+/// The distribution for `id` doing `activity`: the last rule just for them if
+/// there is one, otherwise the last rule for everyone.
+pub fn find_rule(
+    rules: &[DurationRule],
+    id: usize,
+    activity: Activity,
+) -> Option<&DurationDistribution> {
+    let for_activity = || {
+        rules
+            .iter()
+            .rev()
+            .filter(move |rule| rule.activity == activity)
+    };
+    for_activity()
+        .find(|rule| rule.philosopher == Some(id))
+        .or_else(|| for_activity().find(|rule| rule.philosopher.is_none()))
+        .map(|rule| &rule.distribution)
+}
+
+/// A duration from a number of milliseconds, which can't be more than
+/// [`LONGEST`].
+pub(crate) fn millis(value: f64, s: &str) -> Result<Duration, String> {
+    if value.is_nan() || value < 0.0 {
+        Err(format!("Durations can't be negative, got '{s}'"))
+    } else if value > as_millis(LONGEST) {
+        Err(format!(
+            "Durations can't be more than {}ms, got '{s}'",
+            as_millis(LONGEST)
+        ))
+    } else {
+        Ok(Duration::from_secs_f64(value / 1000.0))
+    }
+}
+
+pub(crate) fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Box-Muller: two uniform samples make a normal one.
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+fn truncated_normal<R: Rng + ?Sized>(
+    rng: &mut R,
+    mean: f64,
+    std_dev: f64,
+    min: f64,
+    max: f64,
+) -> f64 {
+    for _ in 0..MAX_REJECTIONS {
+        let x = mean + std_dev * standard_normal(rng);
+        if (min..=max).contains(&x) {
+            return x;
+        }
+    }
+    mean.clamp(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn mean_millis(distribution: &DurationDistribution) -> f64 {
+        let mut rng = StdRng::seed_from_u64(1);
+        let n = 20_000;
+        (0..n)
+            .map(|_| as_millis(distribution.sample(&mut rng)))
+            .sum::<f64>()
+            / n as f64
+    }
+
+    #[test]
+    fn distributions_are_written_the_way_they_are_parsed() {
+        for s in [
+            "fixed:10",
+            "uniform:1,10",
+            "exp:5",
+            "normal:10,3",
+            "normal:10,3,5,15",
+            "lognormal:5,1.5",
+            "bimodal:2,0.5,40,5,0.1",
+        ] {
+            let distribution = DurationDistribution::parse(s).unwrap();
+            assert_eq!(distribution.to_string(), s);
+        }
+    }
+
+    #[test]
+    fn bad_distributions_are_rejected() {
+        for s in [
+            "gamma:1,2",
+            "exp",
+            "exp:-1",
+            "fixed:1e30",
+            "exp:inf",
+            "uniform:1,3600001",
+            "uniform:10,1",
+            "normal:10",
+            "normal:10,3,15,5",
+            "bimodal:2,0.5,40,5,2",
+            "file:/definitely/not/here",
+        ] {
+            assert!(DurationDistribution::parse(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn samples_have_roughly_the_right_mean() {
+        let cases = [
+            ("exp:5", 5.0),
+            ("normal:10,2", 10.0),
+            // The mean of a log-normal is median * exp(sigma^2 / 2).
+            ("lognormal:5,0.5", 5.0 * (0.125f64).exp()),
+            ("bimodal:2,0.1,42,0.1,0.25", 12.0),
+        ];
+        for (s, expected) in cases {
+            let mean = mean_millis(&DurationDistribution::parse(s).unwrap());
+            assert!(
+                (mean - expected).abs() < expected * 0.05,
+                "{s} had a mean of {mean}ms, expected about {expected}ms"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_normals_stay_in_range() {
+        let distribution =
+            DurationDistribution::parse("normal:10,5,8,11").unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let millis = as_millis(distribution.sample(&mut rng));
+            assert!((8.0..=11.0).contains(&millis), "{millis}");
+        }
+    }
+
+    #[test]
+    fn philosophers_own_rules_come_first() {
+        let rules = [
+            DurationRule::parse(Activity::Thinking, "2=fixed:1").unwrap(),
+            DurationRule::parse(Activity::Thinking, "fixed:2").unwrap(),
+            DurationRule::parse(Activity::Thinking, "fixed:3").unwrap(),
+            DurationRule::parse(Activity::Eating, "fixed:4").unwrap(),
+        ];
+        let find = |id, activity| {
+            find_rule(&rules, id, activity).map(ToString::to_string)
+        };
+        assert_eq!(find(2, Activity::Thinking).as_deref(), Some("fixed:1"));
+        assert_eq!(find(1, Activity::Thinking).as_deref(), Some("fixed:3"));
+        assert_eq!(find(2, Activity::Eating).as_deref(), Some("fixed:4"));
+        assert_eq!(find(1, Activity::Eating).as_deref(), Some("fixed:4"));
+        assert_eq!(find_rule(&rules[..1], 1, Activity::Thinking), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! What philosophers report back while a strategy runs.
+
+use crate::contention::FailedAttempts;
+use crate::deadlock::{Deadlock, DeadlockAlarm};
+use crate::Clock;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Duration;
+
+/// Something that happened to a philosopher.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EventKind {
+    StartedThinking,
+    BecameHungry,
+    /// Picked up this fork (numbered from 0, philosopher `i`'s left fork
+    /// being `i - 1` and their right fork `i % n`).
+    AcquiredFork(usize),
+    /// Put this fork down.
+    ReleasedFork(usize),
+    StartedEating,
+    FinishedEating,
+    /// Starved to death.
+    Died,
+}
+
+/// A timestamped event from a philosopher.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// Time since the run started.
+    pub at: Duration,
+    pub id: usize,
+    pub kind: EventKind,
+}
+
+impl fmt::Display for Event {
+    /// For example `1234.567ms philosopher 2 acquired fork 1`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3}ms philosopher {} ",
+            self.at.as_secs_f64() * 1000.0,
+            self.id
+        )?;
+        match self.kind {
+            EventKind::StartedThinking => write!(f, "started thinking"),
+            EventKind::BecameHungry => write!(f, "became hungry"),
+            EventKind::AcquiredFork(fork) => write!(f, "acquired fork {fork}"),
+            EventKind::ReleasedFork(fork) => write!(f, "released fork {fork}"),
+            EventKind::StartedEating => write!(f, "started eating"),
+            EventKind::FinishedEating => write!(f, "finished eating"),
+            EventKind::Died => write!(f, "died"),
+        }
+    }
+}
+
+/// Where philosophers send their events, stamping each one with the time on
+/// the run's clock.
+///
+/// A philosopher should report `AcquiredFork` after it has the fork and
+/// `ReleasedFork` before it lets go, so whoever picks the fork up next can't
+/// get their event in first.
+///
+/// Deadlocks don't come with an event, since nothing happens, so they're
+/// reported to the sink's [`DeadlockAlarm`] instead. Failed attempts to pick
+/// up forks are only counted, as there can be a great many of them.
+#[derive(Clone, Debug)]
+pub struct EventSink {
+    tx: Sender<Event>,
+    clock: Clock,
+    alarm: DeadlockAlarm,
+    attempts: FailedAttempts,
+}
+
+impl EventSink {
+    /// A sink for a table of `n_philosophers`.
+    pub fn new(tx: Sender<Event>, clock: Clock, n_philosophers: usize) -> Self {
+        Self {
+            tx,
+            clock,
+            alarm: DeadlockAlarm::default(),
+            attempts: FailedAttempts::new(n_philosophers),
+        }
+    }
+
+    /// A new sink for a table of `n_philosophers` and the receiving end of
+    /// its events.
+    pub fn channel(
+        clock: Clock,
+        n_philosophers: usize,
+    ) -> (Self, Receiver<Event>) {
+        let (tx, rx) = mpsc::channel();
+        (Self::new(tx, clock, n_philosophers), rx)
+    }
+
+    pub fn emit(&self, id: usize, kind: EventKind) {
+        self.emit_quietly(id, kind);
+        self.clock.changed();
+    }
+
+    /// Like [`EventSink::emit`], but for something no other philosopher
+    /// could be waiting for, so nobody is woken up on a virtual clock.
+    pub(crate) fn emit_quietly(&self, id: usize, kind: EventKind) {
+        let event = Event {
+            at: self.elapsed(),
+            id,
+            kind,
+        };
+        // Nobody listening any more just means the run is over.
+        let _ = self.tx.send(event);
+    }
+
+    pub fn report_deadlock(&self, deadlock: Deadlock) {
+        self.alarm.raise(deadlock);
+        self.clock.changed();
+    }
+
+    /// Where deadlocks reported to this sink (or any clone of it) end up.
+    pub fn deadlock_alarm(&self) -> DeadlockAlarm {
+        self.alarm.clone()
+    }
+
+    /// Philosopher `id` tried to pick up `fork`, but it wasn't free.
+    pub fn failed_attempt(&self, id: usize, fork: usize) {
+        self.attempts.record(id, fork);
+    }
+
+    /// Where failed attempts reported to this sink (or any clone of it) are
+    /// counted.
+    pub fn failed_attempts(&self) -> FailedAttempts {
+        self.attempts.clone()
+    }
+
+    /// Time on the run's clock.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now()
+    }
+}
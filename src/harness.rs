@@ -0,0 +1,528 @@
+use crate::contention::{AttemptCounts, SpinPeriod, SpinWatch};
+use crate::deadlock::Deadlock;
+use crate::histogram::Histogram;
+use crate::invariants::{Checker, Violation};
+use crate::stats::{self, Comparison, Summary, Test};
+use crate::{Clock, Event, EventKind, EventSink, SimConfig, Strategy};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the harness checks for a deadlock while no events are coming
+/// in.
+pub const DEADLOCK_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How a run ended.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The run lasted the full run time.
+    Completed,
+    /// This philosopher starved to death, ending the run early.
+    Starved(usize),
+    /// The strategy stopped sending messages before the run time was up.
+    Disconnected,
+    /// These philosophers were all waiting for each other's forks, so the
+    /// run was stopped there.
+    Deadlocked(Deadlock),
+    /// The strategy let two philosophers have the same fork, or someone eat
+    /// without both forks, so the run was stopped there.
+    Violated(Box<Violation>),
+}
+
+/// How and when a run ended, as far as its events can't show it, so it can
+/// be saved with them. Starving and the forks not being kept apart are there
+/// in the events, but a deadlock or the strategy stopping early aren't.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RunEnd {
+    /// When the run stopped, on its clock.
+    pub at: Duration,
+    /// The deadlock that stopped the run, if one did.
+    pub deadlock: Option<Deadlock>,
+}
+
+/// The result of running a strategy once.
+#[derive(Clone, Debug)]
+pub struct RunReport {
+    pub outcome: Outcome,
+    /// Meals eaten by each philosopher, indexed by `id - 1`.
+    pub meals: Vec<u64>,
+    /// How long each philosopher was hungry for before each of their meals,
+    /// indexed by `id - 1`.
+    pub hunger_waits: Vec<Vec<Duration>>,
+    /// The same hunger waits as a latency histogram per philosopher.
+    pub hunger_histograms: Vec<Histogram>,
+    pub elapsed: Duration,
+    /// The seed the philosophers' random durations came from. Running again
+    /// with it gives everyone the same durations.
+    pub seed: u64,
+    /// Failed attempts to pick up forks, for strategies that poll for them.
+    pub failed_attempts: AttemptCounts,
+    /// Stretches of the run where attempts kept failing but nobody ate.
+    pub spin_periods: Vec<SpinPeriod>,
+}
+
+impl RunReport {
+    pub fn total_meals(&self) -> u64 {
+        self.meals.iter().sum()
+    }
+
+    /// Jain's fairness index over the philosophers' meals.
+    pub fn jain_index(&self) -> Option<f64> {
+        stats::jain_index(&self.meal_samples())
+    }
+
+    /// Meals eaten by the best fed philosopher over the worst fed one.
+    pub fn max_min_ratio(&self) -> Option<f64> {
+        stats::max_min_ratio(&self.meal_samples())
+    }
+
+    /// Summary of each philosopher's hunger waits, in milliseconds.
+    pub fn hunger_wait_summaries(&self) -> Vec<Option<Summary>> {
+        self.hunger_waits
+            .iter()
+            .map(|waits| Summary::from_samples(&millis(waits.iter())))
+            .collect()
+    }
+
+    /// Hunger latency over every philosopher.
+    pub fn hunger_histogram(&self) -> Histogram {
+        merged(self.hunger_histograms.iter())
+    }
+
+    /// How the run ended, for saving with its events.
+    pub fn end(&self) -> RunEnd {
+        RunEnd {
+            at: self.elapsed,
+            deadlock: match &self.outcome {
+                Outcome::Deadlocked(deadlock) => Some(deadlock.clone()),
+                _ => None,
+            },
+        }
+    }
+
+    /// Failed attempts to pick up forks for every meal eaten, or `None` if
+    /// nobody ate.
+    pub fn failed_attempts_per_meal(&self) -> Option<f64> {
+        let meals = self.total_meals();
+        (meals > 0).then(|| self.failed_attempts.total() as f64 / meals as f64)
+    }
+
+    fn meal_samples(&self) -> Vec<f64> {
+        self.meals.iter().map(|&n| n as f64).collect()
+    }
+}
+
+/// The results of running a strategy several times.
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub trials: Vec<RunReport>,
+    /// Total meals per trial, over the trials that ran to completion.
+    pub total_meals: Option<Summary>,
+    /// Meals per trial for each philosopher, over the trials that ran to
+    /// completion.
+    pub meals_per_philosopher: Vec<Option<Summary>>,
+    /// Jain's fairness index per trial, over the trials that ran to
+    /// completion.
+    pub jain_index: Option<Summary>,
+    /// Max/min meal ratio per trial, over the trials that ran to completion
+    /// and where everyone ate at least once.
+    pub max_min_ratio: Option<Summary>,
+    /// Trials that ran to completion where somebody ate nothing, so the
+    /// max/min meal ratio was infinite and isn't in `max_min_ratio`.
+    pub unfed_trials: usize,
+    /// Each philosopher's hunger waits in milliseconds, pooled over the
+    /// trials that ran to completion.
+    pub hunger_wait_millis: Vec<Option<Summary>>,
+    /// Each philosopher's hunger latency histogram, merged over the trials
+    /// that ran to completion.
+    pub hunger_histograms: Vec<Histogram>,
+    /// Failed attempts to pick up forks per meal, over the trials that ran
+    /// to completion.
+    pub failed_attempts_per_meal: Option<Summary>,
+}
+
+impl BenchReport {
+    pub fn from_trials(trials: Vec<RunReport>) -> Self {
+        let completed: Vec<&RunReport> = trials
+            .iter()
+            .filter(|trial| trial.outcome == Outcome::Completed)
+            .collect();
+        let n_philosophers = trials.first().map_or(0, |t| t.meals.len());
+
+        let totals = total_meal_samples(&completed);
+        let meals_per_philosopher = (0..n_philosophers)
+            .map(|i| {
+                let meals: Vec<f64> = completed
+                    .iter()
+                    .map(|trial| trial.meals[i] as f64)
+                    .collect();
+                Summary::from_samples(&meals)
+            })
+            .collect();
+        let jain_indices: Vec<f64> = completed
+            .iter()
+            .filter_map(|trial| trial.jain_index())
+            .collect();
+        let (ratios, unfed): (Vec<f64>, Vec<f64>) = completed
+            .iter()
+            .filter_map(|trial| trial.max_min_ratio())
+            .partition(|ratio| ratio.is_finite());
+        let hunger_wait_millis = (0..n_philosophers)
+            .map(|i| {
+                let waits =
+                    millis(completed.iter().flat_map(|t| &t.hunger_waits[i]));
+                Summary::from_samples(&waits)
+            })
+            .collect();
+        let hunger_histograms = (0..n_philosophers)
+            .map(|i| merged(completed.iter().map(|t| &t.hunger_histograms[i])))
+            .collect();
+        let attempts_per_meal: Vec<f64> = completed
+            .iter()
+            .filter_map(|trial| trial.failed_attempts_per_meal())
+            .collect();
+
+        Self {
+            total_meals: Summary::from_samples(&totals),
+            meals_per_philosopher,
+            jain_index: Summary::from_samples(&jain_indices),
+            max_min_ratio: Summary::from_samples(&ratios),
+            unfed_trials: unfed.len(),
+            hunger_wait_millis,
+            hunger_histograms,
+            failed_attempts_per_meal: Summary::from_samples(&attempts_per_meal),
+            trials,
+        }
+    }
+
+    /// Total meals for each trial that ran to completion.
+    pub fn total_meal_samples(&self) -> Vec<f64> {
+        let completed: Vec<&RunReport> = self
+            .trials
+            .iter()
+            .filter(|trial| trial.outcome == Outcome::Completed)
+            .collect();
+        total_meal_samples(&completed)
+    }
+
+    /// Test whether this strategy's throughput really differs from
+    /// `other`'s, or `None` if too few trials completed to tell.
+    pub fn compare(
+        &self,
+        other: &BenchReport,
+        test: Test,
+    ) -> Option<Comparison> {
+        test.compare(&self.total_meal_samples(), &other.total_meal_samples())
+    }
+
+    /// Hunger latency over every philosopher.
+    pub fn hunger_histogram(&self) -> Histogram {
+        merged(self.hunger_histograms.iter())
+    }
+
+    /// Trials that were cut short, e.g. because someone starved.
+    pub fn failed_trials(&self) -> impl Iterator<Item = &RunReport> {
+        self.trials
+            .iter()
+            .filter(|trial| trial.outcome != Outcome::Completed)
+    }
+}
+
+fn total_meal_samples(trials: &[&RunReport]) -> Vec<f64> {
+    trials
+        .iter()
+        .map(|trial| trial.total_meals() as f64)
+        .collect()
+}
+
+fn merged<'a>(histograms: impl Iterator<Item = &'a Histogram>) -> Histogram {
+    let mut total = Histogram::new();
+    for histogram in histograms {
+        total.merge(histogram);
+    }
+    total
+}
+
+fn millis<'a>(durations: impl Iterator<Item = &'a Duration>) -> Vec<f64> {
+    durations.map(|d| d.as_secs_f64() * 1000.0).collect()
+}
+
+/// Meals and hunger waits counted from a stream of events, which are
+/// checked along the way.
+struct Tally {
+    checker: Checker,
+    meals: Vec<u64>,
+    hungry_since: Vec<Option<Duration>>,
+    hunger_waits: Vec<Vec<Duration>>,
+    hunger_histograms: Vec<Histogram>,
+    spin: SpinWatch,
+}
+
+impl Tally {
+    fn new(n_philosophers: usize) -> Self {
+        Self {
+            checker: Checker::new(n_philosophers),
+            meals: vec![0; n_philosophers],
+            hungry_since: vec![None; n_philosophers],
+            hunger_waits: vec![vec![]; n_philosophers],
+            hunger_histograms: vec![Histogram::new(); n_philosophers],
+            spin: SpinWatch::new(),
+        }
+    }
+
+    /// Count the event, returning how the run ended if it ends the run.
+    fn record(&mut self, event: &Event) -> Option<Outcome> {
+        if let Err(violation) = self.checker.check(event) {
+            return Some(Outcome::Violated(violation));
+        }
+        let seat = event.id - 1;
+        match event.kind {
+            EventKind::BecameHungry => self.hungry_since[seat] = Some(event.at),
+            EventKind::StartedEating => {
+                if let Some(since) = self.hungry_since[seat].take() {
+                    let wait = event.at.saturating_sub(since);
+                    self.hunger_waits[seat].push(wait);
+                    self.hunger_histograms[seat].record(wait);
+                }
+            }
+            EventKind::FinishedEating => {
+                self.meals[seat] += 1;
+                self.spin.meal();
+            }
+            EventKind::Died => return Some(Outcome::Starved(event.id)),
+            _ => {}
+        }
+        None
+    }
+}
+
+/// Run a strategy for the configured amount of time, counting how many meals
+/// each philosopher eats.
+pub fn run(strategy: &dyn Strategy, config: &SimConfig) -> RunReport {
+    run_with(strategy, config, |_| {})
+}
+
+/// Like [`run`], but also returns every event from the run, in the order
+/// they arrived.
+pub fn run_recorded(
+    strategy: &dyn Strategy,
+    config: &SimConfig,
+) -> (RunReport, Vec<Event>) {
+    let mut events = vec![];
+    let report = run_with(strategy, config, |event| events.push(*event));
+    (report, events)
+}
+
+/// Like [`run`], but every event is also passed to `on_event` as it arrives.
+pub fn run_with<F>(
+    strategy: &dyn Strategy,
+    config: &SimConfig,
+    mut on_event: F,
+) -> RunReport
+where
+    F: FnMut(&Event),
+{
+    let seed = config.seed.unwrap_or_else(rand::random);
+    let config = &SimConfig {
+        seed: Some(seed),
+        ..config.clone()
+    };
+    let clock = Clock::new(config.virtual_time);
+    let (sink, rx) = EventSink::channel(clock.clone(), config.n_philosophers);
+    let alarm = sink.deadlock_alarm();
+    let attempts = sink.failed_attempts();
+    let kill_switch = Arc::new(AtomicBool::new(false));
+    let run_time = Duration::from_secs(config.run_time_seconds);
+    let mut tally = Tally::new(config.n_philosophers);
+
+    let (outcome, elapsed) = thread::scope(|scope| {
+        let cloned_kill_switch = kill_switch.clone();
+        let strategy_clock = clock.clone();
+        clock.spawn_scoped(scope, move || {
+            strategy.run(config, strategy_clock, sink, cloned_kill_switch)
+        });
+
+        let mut outcome = Outcome::Completed;
+        while let Some(remaining) = run_time.checked_sub(clock.now()) {
+            if let Some(deadlock) = alarm.raised() {
+                outcome = Outcome::Deadlocked(deadlock);
+                break;
+            }
+            tally.spin.update(clock.now(), attempts.total());
+            let timeout = remaining.min(DEADLOCK_CHECK_INTERVAL);
+            match clock.recv_timeout(&rx, timeout) {
+                Ok(event) => {
+                    on_event(&event);
+                    if let Some(ended) = tally.record(&event) {
+                        outcome = ended;
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) if timeout == remaining => break,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    outcome = Outcome::Disconnected;
+                    break;
+                }
+            }
+        }
+        let elapsed = clock.now();
+
+        // Everyone else has to stop too before the scope can end, and with
+        // virtual time they can't get on with it until this thread is out of
+        // the way.
+        kill_switch.store(true, Ordering::Relaxed);
+        clock.leave();
+        (outcome, elapsed)
+    });
+
+    let spin_periods = tally.spin.finish(elapsed, attempts.total());
+    let failed_attempts = attempts.counts();
+    RunReport {
+        outcome,
+        meals: tally.meals,
+        hunger_waits: tally.hunger_waits,
+        hunger_histograms: tally.hunger_histograms,
+        elapsed,
+        seed,
+        failed_attempts,
+        spin_periods,
+    }
+}
+
+/// Count the meals in a recorded run the same way [`run`] counted them as
+/// the events arrived, passing each event to `on_event` first. Anything after
+/// the run ended or after someone died is left out, as it would have been.
+/// How the run ended comes from `end` when the events don't show it.
+pub fn replay<F>(
+    config: &SimConfig,
+    events: &[Event],
+    end: &RunEnd,
+    mut on_event: F,
+) -> RunReport
+where
+    F: FnMut(&Event),
+{
+    let run_time = Duration::from_secs(config.run_time_seconds);
+    let mut tally = Tally::new(config.n_philosophers);
+    let mut outcome = match &end.deadlock {
+        Some(deadlock) => Outcome::Deadlocked(deadlock.clone()),
+        None if end.at < run_time => Outcome::Disconnected,
+        None => Outcome::Completed,
+    };
+    let mut elapsed = end.at;
+    for event in events.iter().take_while(|event| event.at <= end.at) {
+        on_event(event);
+        if let Some(ended) = tally.record(event) {
+            outcome = ended;
+            elapsed = event.at;
+            break;
+        }
+    }
+
+    RunReport {
+        outcome,
+        meals: tally.meals,
+        hunger_waits: tally.hunger_waits,
+        hunger_histograms: tally.hunger_histograms,
+        elapsed,
+        seed: config.seed.unwrap_or_default(),
+        // Traces don't record failed attempts.
+        failed_attempts: AttemptCounts {
+            by_philosopher: vec![0; config.n_philosophers],
+            by_fork: vec![0; config.n_philosophers],
+        },
+        spin_periods: vec![],
+    }
+}
+
+/// Run a strategy `trials` times and summarise the results. Each trial gets
+/// its own seed, see [`trial_seed`].
+pub fn benchmark(
+    strategy: &dyn Strategy,
+    config: &SimConfig,
+    trials: usize,
+) -> BenchReport {
+    let base_seed = config.seed.unwrap_or_else(rand::random);
+    let trials = (0..trials)
+        .map(|trial| {
+            let config = SimConfig {
+                seed: Some(trial_seed(base_seed, trial)),
+                ..config.clone()
+            };
+            run(strategy, &config)
+        })
+        .collect();
+    BenchReport::from_trials(trials)
+}
+
+/// The seed for one of a set of trials starting from `base_seed`, so the
+/// whole set can be repeated from the first trial's seed.
+pub fn trial_seed(base_seed: u64, trial: usize) -> u64 {
+    base_seed.wrapping_add(trial as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Registry;
+
+    #[test]
+    fn builtin_strategies_keep_the_forks_apart() {
+        let config = SimConfig {
+            run_time_seconds: 2,
+            random: true,
+            virtual_time: true,
+            ..SimConfig::default()
+        };
+        for strategy in Registry::builtin().iter() {
+            let report = run(strategy, &config);
+            if let Outcome::Violated(violation) = report.outcome {
+                panic!(
+                    "{} (seed {}): {violation}",
+                    strategy.name(),
+                    report.seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn trials_where_somebody_ate_nothing_are_counted() {
+        let config = SimConfig {
+            run_time_seconds: 1,
+            virtual_time: true,
+            seed: Some(7),
+            ..SimConfig::default()
+        };
+        let fed = run(&crate::two_forks::TwoForks, &config);
+        assert_eq!(fed.outcome, Outcome::Completed);
+        let mut unfed = fed.clone();
+        unfed.meals[0] = 0;
+
+        let report = BenchReport::from_trials(vec![fed, unfed]);
+        assert_eq!(report.max_min_ratio.map(|ratio| ratio.n), Some(1));
+        assert_eq!(report.unfed_trials, 1);
+    }
+
+    #[test]
+    fn replaying_a_run_counts_the_same_meals() {
+        let config = SimConfig {
+            run_time_seconds: 1,
+            random: true,
+            virtual_time: true,
+            seed: Some(7),
+            ..SimConfig::default()
+        };
+        for strategy in Registry::builtin().iter() {
+            let (report, events) = run_recorded(strategy, &config);
+            let replayed = replay(&config, &events, &report.end(), |_| {});
+            assert_eq!(replayed.outcome, report.outcome, "{}", strategy.name());
+            assert_eq!(replayed.elapsed, report.elapsed, "{}", strategy.name());
+            assert_eq!(replayed.meals, report.meals, "{}", strategy.name());
+            assert_eq!(replayed.hunger_waits, report.hunger_waits);
+        }
+    }
+}
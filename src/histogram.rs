@@ -0,0 +1,148 @@
+//! Log-bucketed latency histograms, in the style of HdrHistogram.
+//!
+//! Values are recorded in microseconds. Every power of two is split into the
+//! same number of linear sub-buckets, so a recorded value is never more than
+//! 1% away from the top of the bucket it ends up in, however large it is, and the
+//! histogram never grows past a few thousand counters.
+
+use std::time::Duration;
+
+/// Each power of two is split into `2^SUB_BUCKET_BITS` sub-buckets.
+const SUB_BUCKET_BITS: u32 = 8;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+/// The quantiles printed in reports.
+pub const REPORTED_QUANTILES: [(f64, &str); 4] =
+    [(0.5, "p50"), (0.9, "p90"), (0.99, "p99"), (0.999, "p99.9")];
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let micros = u64::try_from(value.as_micros()).unwrap_or(u64::MAX);
+        self.record_micros(micros);
+    }
+
+    pub fn record_micros(&mut self, micros: u64) {
+        let index = bucket_index(micros);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+        self.max = self.max.max(micros);
+    }
+
+    /// Add everything recorded in `other` to this histogram.
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    /// Number of values recorded.
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// The largest value recorded, exactly.
+    pub fn max(&self) -> Option<Duration> {
+        (!self.is_empty()).then(|| Duration::from_micros(self.max))
+    }
+
+    /// The value that `quantile` (between 0 and 1) of the recorded values are
+    /// less than or equal to, rounded up to the top of its bucket. `None` if
+    /// nothing has been recorded.
+    pub fn value_at_quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.is_empty() {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.total as f64).ceil()
+            as u64)
+            .max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let micros = bucket_highest_value(index).min(self.max);
+                return Some(Duration::from_micros(micros));
+            }
+        }
+        self.max()
+    }
+}
+
+/// Values below `SUB_BUCKETS` get a bucket each. Above that, each power of two
+/// gets `SUB_BUCKETS / 2` buckets, since the top bit is always set.
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS as u64 {
+        return micros as usize;
+    }
+    let magnitude = 63 - micros.leading_zeros();
+    let shift = magnitude + 1 - SUB_BUCKET_BITS;
+    let sub_bucket = (micros >> shift) as usize;
+    shift as usize * SUB_BUCKETS / 2 + sub_bucket
+}
+
+/// The largest value that ends up in the bucket at `index`.
+fn bucket_highest_value(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let half = SUB_BUCKETS / 2;
+    let shift = (index - SUB_BUCKETS) / half + 1;
+    let sub_bucket = (index - SUB_BUCKETS) % half + half;
+    ((sub_bucket as u64) << shift) | ((1 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_cover_every_value_in_order() {
+        let mut previous_index = 0;
+        for micros in (0..100_000).chain([u64::MAX / 2, u64::MAX]) {
+            let index = bucket_index(micros);
+            assert!(index >= previous_index);
+            assert!(bucket_highest_value(index) >= micros);
+            // Within 1% of the value (or exact, below 256 microseconds).
+            let error = bucket_highest_value(index) - micros;
+            assert!(error as f64 <= micros as f64 / 128.0, "{micros}");
+            previous_index = index;
+        }
+    }
+
+    #[test]
+    fn quantiles_are_close_to_the_recorded_values() {
+        let mut histogram = Histogram::new();
+        for micros in 1..=10_000 {
+            histogram.record_micros(micros);
+        }
+        for (quantile, expected) in [(0.5, 5_000), (0.9, 9_000), (0.999, 9_990)]
+        {
+            let value = histogram.value_at_quantile(quantile).unwrap();
+            let error = value.as_micros() as f64 / expected as f64 - 1.0;
+            assert!(error.abs() < 0.01, "{quantile}: {value:?}");
+        }
+        assert_eq!(histogram.max(), Some(Duration::from_micros(10_000)));
+    }
+}
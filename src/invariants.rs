@@ -0,0 +1,240 @@
+//! Checking, as the events come in, that a strategy really keeps the forks
+//! to one philosopher at a time.
+//!
+//! Every event is checked against what the events before it said: a fork
+//! can't be picked up while somebody else is holding it or put down by
+//! somebody who isn't, and a philosopher can only start eating with both of
+//! their forks in hand and neither neighbour eating. This relies on
+//! philosophers reporting `AcquiredFork` after they have the fork and
+//! `ReleasedFork` before they let go, see [`EventSink`](crate::EventSink).
+
+use crate::{Event, EventKind};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// How many of the events leading up to a violation are kept to show what
+/// happened.
+pub const HISTORY: usize = 20;
+
+/// Something the events say happened that never should have.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Breach {
+    /// The philosopher picked up a fork that someone else was holding.
+    ForkTaken { fork: usize, holder: usize },
+    /// The philosopher put down a fork they weren't holding.
+    ForkNotHeld { fork: usize },
+    /// The philosopher started eating without holding this fork.
+    AteWithoutFork { fork: usize },
+    /// The philosopher started eating while this neighbour was eating.
+    NeighbourEating { neighbour: usize },
+}
+
+/// A broken invariant and the events leading up to it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Violation {
+    pub breach: Breach,
+    /// The event that broke it.
+    pub event: Event,
+    /// Up to [`HISTORY`] events before it, oldest first.
+    pub history: Vec<Event>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = self.event.id;
+        match self.breach {
+            Breach::ForkTaken { fork, holder } => write!(
+                f,
+                "Philosopher {id} picked up fork {fork} while philosopher \
+                 {holder} was holding it"
+            )?,
+            Breach::ForkNotHeld { fork } => write!(
+                f,
+                "Philosopher {id} put down fork {fork} without holding it"
+            )?,
+            Breach::AteWithoutFork { fork } => write!(
+                f,
+                "Philosopher {id} started eating without holding fork {fork}"
+            )?,
+            Breach::NeighbourEating { neighbour } => write!(
+                f,
+                "Philosopher {id} started eating while their neighbour, \
+                 philosopher {neighbour}, was eating"
+            )?,
+        }
+        writeln!(f, ". The last {} events were:", self.history.len() + 1)?;
+        for event in &self.history {
+            writeln!(f, "\t{event}")?;
+        }
+        write!(f, "\t{} <-", self.event)
+    }
+}
+
+/// Keeps track of who holds which fork and who's eating, see the
+/// [module docs](self).
+#[derive(Clone, Debug)]
+pub struct Checker {
+    /// Who's holding each fork, indexed by fork number.
+    holders: Vec<Option<usize>>,
+    /// Whether each philosopher is eating, indexed by `id - 1`.
+    eating: Vec<bool>,
+    history: VecDeque<Event>,
+}
+
+impl Checker {
+    pub fn new(n_philosophers: usize) -> Self {
+        Self {
+            holders: vec![None; n_philosophers],
+            eating: vec![false; n_philosophers],
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Check the next event, returning what it broke if it broke anything.
+    pub fn check(&mut self, event: &Event) -> Result<(), Box<Violation>> {
+        let result = self.breach(event).map_or(Ok(()), |breach| {
+            Err(Box::new(Violation {
+                breach,
+                event: *event,
+                history: self.history.iter().copied().collect(),
+            }))
+        });
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(*event);
+        result
+    }
+
+    /// Apply the event, or say what's wrong with it.
+    fn breach(&mut self, event: &Event) -> Option<Breach> {
+        let n = self.holders.len();
+        let id = event.id;
+        match event.kind {
+            EventKind::AcquiredFork(fork) => match self.holders[fork] {
+                Some(holder) if holder != id => {
+                    return Some(Breach::ForkTaken { fork, holder })
+                }
+                _ => self.holders[fork] = Some(id),
+            },
+            EventKind::ReleasedFork(fork) => {
+                if self.holders[fork] != Some(id) {
+                    return Some(Breach::ForkNotHeld { fork });
+                }
+                self.holders[fork] = None;
+            }
+            EventKind::StartedEating => {
+                for fork in [(id - 1) % n, id % n] {
+                    if self.holders[fork] != Some(id) {
+                        return Some(Breach::AteWithoutFork { fork });
+                    }
+                }
+                for neighbour in [(id + n - 2) % n + 1, id % n + 1] {
+                    if self.eating[neighbour - 1] {
+                        return Some(Breach::NeighbourEating { neighbour });
+                    }
+                }
+                self.eating[id - 1] = true;
+            }
+            EventKind::FinishedEating | EventKind::Died => {
+                self.eating[id - 1] = false
+            }
+            EventKind::StartedThinking | EventKind::BecameHungry => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn events(events: &[(usize, EventKind)]) -> Vec<Event> {
+        events
+            .iter()
+            .enumerate()
+            .map(|(i, &(id, kind))| Event {
+                at: Duration::from_millis(i as u64),
+                id,
+                kind,
+            })
+            .collect()
+    }
+
+    /// The first breach in the events, if there is one.
+    fn first_breach(events: &[Event]) -> Option<Breach> {
+        let mut checker = Checker::new(3);
+        events
+            .iter()
+            .find_map(|event| checker.check(event).err())
+            .map(|violation| violation.breach)
+    }
+
+    #[test]
+    fn a_proper_meal_is_fine() {
+        use EventKind::*;
+        let events = events(&[
+            (1, BecameHungry),
+            (1, AcquiredFork(0)),
+            (1, AcquiredFork(1)),
+            (1, StartedEating),
+            (1, FinishedEating),
+            (1, ReleasedFork(1)),
+            (2, AcquiredFork(1)),
+            (2, AcquiredFork(2)),
+            (2, StartedEating),
+            (1, ReleasedFork(0)),
+        ]);
+        assert_eq!(first_breach(&events), None);
+    }
+
+    #[test]
+    fn breaches_are_caught() {
+        use EventKind::*;
+        let cases = [
+            (
+                vec![(1, AcquiredFork(1)), (2, AcquiredFork(1))],
+                Breach::ForkTaken { fork: 1, holder: 1 },
+            ),
+            (vec![(2, ReleasedFork(1))], Breach::ForkNotHeld { fork: 1 }),
+            (
+                // Like eating after only one `try_lock` worked.
+                vec![(1, AcquiredFork(1)), (1, StartedEating)],
+                Breach::AteWithoutFork { fork: 0 },
+            ),
+        ];
+        for (case, expected) in cases {
+            assert_eq!(first_breach(&events(&case)), Some(expected));
+        }
+
+        // Neighbours can only eat together if the forks are already wrong,
+        // so check the neighbour rule on its own.
+        let mut checker = Checker::new(3);
+        checker.eating[0] = true;
+        checker.holders = vec![Some(3), Some(2), Some(2)];
+        let event = events(&[(2, StartedEating)])[0];
+        let violation = checker.check(&event).unwrap_err();
+        assert_eq!(violation.breach, Breach::NeighbourEating { neighbour: 1 });
+    }
+
+    #[test]
+    fn violations_show_the_last_few_events() {
+        let mut checker = Checker::new(3);
+        let mut history = events(&[(1, EventKind::BecameHungry); HISTORY + 5]);
+        history.push(Event {
+            at: Duration::from_millis(100),
+            id: 2,
+            kind: EventKind::ReleasedFork(0),
+        });
+        let violation = history
+            .iter()
+            .find_map(|event| checker.check(event).err())
+            .unwrap();
+        assert_eq!(violation.history.len(), HISTORY);
+        assert_eq!(violation.history[..], history[5..HISTORY + 5]);
+        assert!(violation
+            .to_string()
+            .ends_with("100.000ms philosopher 2 released fork 0 <-"));
+    }
+}
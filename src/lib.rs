@@ -0,0 +1,251 @@
+//! The dining philosophers problem, solved a few different ways so the
+//! solutions can be compared against each other.
+//!
+//! Each solution lives in its own module and implements [`Strategy`], the
+//! [`Registry`] collects them by name, and [`harness`] runs strategies (once or
+//! over several trials) and counts how many meals were eaten.
+
+pub mod arbitrator;
+pub mod backoff;
+pub mod break_symmetry;
+pub mod chandy_misra;
+pub mod chrome_trace;
+pub mod clock;
+pub mod contention;
+pub mod deadlock;
+pub mod distribution;
+pub mod events;
+pub mod harness;
+pub mod histogram;
+pub mod invariants;
+pub mod registry;
+pub mod report;
+pub mod resource_hierarchy;
+pub mod semaphores;
+pub mod sequential;
+pub mod stats;
+pub mod svg;
+pub mod timeline;
+pub mod trace;
+pub mod two_forks;
+pub mod visualize;
+
+pub use backoff::{Backoff, BackoffPolicy};
+pub use clock::Clock;
+pub use distribution::{Activity, DurationDistribution, DurationRule};
+pub use events::{Event, EventKind, EventSink};
+pub use registry::{Properties, Registry};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cell::RefMut;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A solution to the dining philosophers problem.
+pub trait Strategy: Sync {
+    /// Short name, used to pick the strategy on the command line.
+    fn name(&self) -> &'static str;
+
+    /// One line explanation of how the strategy works.
+    fn description(&self) -> &'static str;
+
+    /// What the strategy guarantees.
+    fn properties(&self) -> Properties;
+
+    /// Heading printed above the strategy's results.
+    fn title(&self) -> String {
+        self.name().replace('_', " ").to_uppercase()
+    }
+
+    /// Seat the philosophers and let them think and eat, until `cancel` is
+    /// set or someone starves. Everything that happens to a philosopher is
+    /// reported to `sink`. All sleeping, waiting and starting threads goes
+    /// through `clock`.
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    );
+}
+
+/// Parameters for a simulation, shared by every strategy.
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub n_philosophers: usize,
+    /// Maximum number of milliseconds a philosopher can think or eat for
+    pub max_duration_millis: u64,
+    /// Minimum number of milliseconds a philosopher can think or eat for
+    pub min_duration_millis: u64,
+    /// Philosopher will die if they're hungry for longer than this time
+    /// (milliseconds)
+    pub hunger_threshold_millis: u64,
+    pub run_time_seconds: u64,
+    /// Think and eat for a random amount of time, rather than always for the
+    /// maximum duration. See [`SimConfig::distribution`].
+    pub random: bool,
+    /// Distributions to use instead of the uniform one with random timing.
+    pub durations: Vec<DurationRule>,
+    /// Run on a virtual clock, so nobody really sleeps and a run with fixed
+    /// timing always turns out the same. See [`clock`].
+    pub virtual_time: bool,
+    /// Where every philosopher's random durations come from. The harness
+    /// picks one if there isn't one, so every run can be repeated.
+    pub seed: Option<u64>,
+    /// What philosophers do after failing to pick up their forks, in the
+    /// strategies that poll for them.
+    pub backoff: BackoffPolicy,
+}
+
+impl SimConfig {
+    /// Check the parameters make sense together.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.n_philosophers < 2 {
+            return Err("There must be at least two philosophers".to_string());
+        }
+        if self.min_duration_millis >= self.max_duration_millis {
+            return Err(format!(
+                "Minimum duration ({}ms) must be less than the maximum \
+                 duration ({}ms)",
+                self.min_duration_millis, self.max_duration_millis
+            ));
+        }
+        if self.run_time_seconds == 0 {
+            return Err("Run time must be at least one second".to_string());
+        }
+        for rule in &self.durations {
+            match rule.philosopher {
+                Some(id) if id == 0 || id > self.n_philosophers => {
+                    return Err(format!(
+                        "There's no philosopher {id} at a table of {}",
+                        self.n_philosophers
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// How long philosopher `id` spends doing `activity`: always the maximum
+    /// duration with fixed timing, and with random timing whichever of
+    /// [`SimConfig::durations`] applies or else anything from the minimum up
+    /// to the maximum.
+    pub fn distribution(
+        &self,
+        id: usize,
+        activity: Activity,
+    ) -> DurationDistribution {
+        let max = Duration::from_millis(self.max_duration_millis);
+        if !self.random {
+            return DurationDistribution::Fixed(max);
+        }
+        distribution::find_rule(&self.durations, id, activity)
+            .cloned()
+            .unwrap_or(DurationDistribution::Uniform {
+                min: Duration::from_millis(self.min_duration_millis),
+                max,
+            })
+    }
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        let max_duration_millis = 10;
+        Self {
+            n_philosophers: 5,
+            max_duration_millis,
+            min_duration_millis: max_duration_millis / 10,
+            hunger_threshold_millis: max_duration_millis * 10,
+            run_time_seconds: 10,
+            random: false,
+            durations: vec![],
+            virtual_time: false,
+            seed: None,
+            backoff: BackoffPolicy::None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+pub enum PhilosopherState {
+    Eating,
+    /// Hungry since this time on the philosopher's clock.
+    Hungry(Duration),
+    Thinking,
+    Dead,
+}
+
+pub trait Diner {
+    /// Begin the cycle of thinking and eating. Only ends if the philosopher
+    /// starves to death or if the killswitch is activated.
+    fn run(&mut self) {
+        while self.current_state() != PhilosopherState::Dead
+            && !self.is_kill_switch_active()
+        {
+            self.think();
+            self.eat();
+        }
+    }
+
+    fn id(&self) -> usize;
+
+    /// Report something that happened to the philosopher.
+    fn emit(&self, kind: EventKind);
+
+    fn think(&mut self);
+
+    fn eat(&mut self);
+
+    fn current_state(&self) -> PhilosopherState;
+
+    fn config(&self) -> &SimConfig;
+
+    fn clock(&self) -> &Clock;
+
+    /// The philosopher's own random number generator, see
+    /// [`philosopher_rng`].
+    fn rng(&self) -> RefMut<'_, StdRng>;
+
+    fn is_kill_switch_active(&self) -> bool;
+
+    /// Has the philosopher been hungry for longer than the maximum time?
+    fn has_starved_to_death(&self) -> bool {
+        if let PhilosopherState::Hungry(hungry_since) = self.current_state() {
+            let threshold =
+                Duration::from_millis(self.config().hunger_threshold_millis);
+            self.clock().now().saturating_sub(hungry_since) > threshold
+        } else {
+            false
+        }
+    }
+
+    /// Eat or think for a random amount of time.
+    fn sleep(&self, activity: Activity) {
+        self.clock().sleep(self.generate_duration(activity));
+    }
+
+    fn generate_duration(&self, activity: Activity) -> Duration {
+        self.config()
+            .distribution(self.id(), activity)
+            .sample(&mut *self.rng())
+    }
+}
+
+/// A random number generator for one philosopher, so each philosopher gets
+/// their own stream of durations that doesn't depend on how often anyone
+/// else has rolled the dice. The same seed and id always give the same
+/// stream. With no seed the stream is different every time.
+pub fn philosopher_rng(seed: Option<u64>, id: usize) -> StdRng {
+    match seed {
+        // Spread the ids out so neighbouring philosophers' seeds don't look
+        // alike.
+        Some(seed) => StdRng::seed_from_u64(
+            seed ^ (id as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+        ),
+        None => StdRng::from_entropy(),
+    }
+}
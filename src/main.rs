@@ -1,166 +1,299 @@
-mod break_symmetry;
-mod resource_hierarchy;
-mod semaphores;
-mod sequential;
-mod two_forks;
+mod cli;
 
-use rand::{thread_rng, Rng};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Sender, TryRecvError};
-use std::sync::{mpsc, Arc};
+use dining_philosophers::harness::{self, BenchReport};
+use dining_philosophers::svg::{self, Window};
+use dining_philosophers::trace::{self, TraceHeader};
+use dining_philosophers::visualize::LiveTable;
+use dining_philosophers::{
+    chrome_trace, report, Event, Properties, Registry, SimConfig, Strategy,
+};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::thread;
-use std::time::{Duration, Instant};
-
-const N_PHILOSOPHERS: usize = 5;
-
-// Maximum number of milliseconds a philosopher can think or eat for
-const MAX_DURATION_MILLIS: u64 = 10;
-// Minimum number of milliseconds a philosopher can think or eat for
-#[allow(dead_code)]
-const MIN_DURATION_MILLIS: u64 = MAX_DURATION_MILLIS / 10;
-// Philosopher will die if they're hungry for longer than this time (milliseconds)
-const HUNGER_THRESHOLD_MILLIS: u128 = MAX_DURATION_MILLIS as u128 * 10;
-
-const RUN_TIME_SECONDS: u64 = 10;
+use std::time::Instant;
 
 fn main() {
-    println!("~~SEQUENTIAL (CONTROL)~~ [no randomness]");
-    run(sequential::main, false);
-
-    println!("\n~~TWO FORKS~~ [no randomness]");
-    run(two_forks::main, false);
-
-    println!("\n~~BREAK SYMMETRY~~ [no randomness]");
-    run(break_symmetry::main, false);
-
-    println!("\n~~DIJKSTRA'S SEMAPHORES~~ [no randomness]");
-    run(semaphores::main, false);
-
-    println!("\n~~RESOURCE HIERARCHY~~ [no randomness]");
-    run(resource_hierarchy::main, false);
-
-    println!("\n~~SEQUENTIAL (CONTROL)~~ [with randomness]");
-    run(sequential::main, true);
+    let registry = Registry::builtin();
+    let args = match cli::parse(&registry, std::env::args().skip(1)) {
+        Ok(cli::Command::Run(args)) => args,
+        Ok(cli::Command::Replay(args)) => {
+            replay(&registry, &args);
+            return;
+        }
+        Ok(cli::Command::List) => {
+            list_strategies(&registry);
+            return;
+        }
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    println!("\n~~TWO FORKS~~ [with randomness]");
-    run(two_forks::main, true);
+    let single_run =
+        args.timings.len() * args.strategies.len() * args.trials == 1;
+    let exports: Vec<(Export, &PathBuf)> = [
+        (Export::Trace, &args.trace),
+        (Export::ChromeTrace, &args.chrome_trace),
+        (Export::Svg(args.svg_window), &args.svg),
+    ]
+    .into_iter()
+    .filter_map(|(export, path)| Some((export, path.as_ref()?)))
+    .collect();
+    // Every strategy gets the same seeds, so they're all compared on the
+    // same durations.
+    let base_seed = args.config.seed.unwrap_or_else(rand::random);
+    let mut first = true;
+    for &random in &args.timings {
+        let randomness = if random { "with" } else { "no" };
+        let config = SimConfig {
+            random,
+            ..args.config.clone()
+        };
+        let mut benchmarks = vec![];
+        for strategy in &args.strategies {
+            if !first {
+                println!();
+            }
+            first = false;
+            if args.trials == 1 {
+                println!("~~{}~~ [{randomness} randomness]", strategy.title());
+            } else {
+                println!(
+                    "~~{}~~ [{randomness} randomness] ({} trials)",
+                    strategy.title(),
+                    args.trials
+                );
+            }
 
-    println!("\n~~BREAK SYMMETRY~~ [with randomness]");
-    run(break_symmetry::main, true);
+            let mut trials = vec![];
+            let mut saved = vec![];
+            for trial in 0..args.trials {
+                let config = SimConfig {
+                    seed: Some(harness::trial_seed(base_seed, trial)),
+                    ..config.clone()
+                };
+                let report = if exports.is_empty() && !args.visualize {
+                    harness::run(*strategy, &config)
+                } else {
+                    let (report, events) = run_observed(
+                        *strategy,
+                        &config,
+                        &args,
+                        !exports.is_empty(),
+                    );
+                    let header = TraceHeader {
+                        strategy: strategy.name().to_string(),
+                        config: config.clone(),
+                        seed: Some(report.seed),
+                        end: report.end(),
+                    };
+                    for &(export, base) in &exports {
+                        let path = if single_run {
+                            base.clone()
+                        } else {
+                            export_path(base, *strategy, random, trial, &args)
+                        };
+                        export.save(&path, &header, &events);
+                        saved.push((export, path));
+                    }
+                    report
+                };
+                trials.push(report);
+            }
 
-    println!("\n~~DIJKSTRA'S SEMAPHORES~~ [with randomness]");
-    run(semaphores::main, true);
+            if args.trials == 1 {
+                report::print_run(&trials[0]);
+            } else {
+                let bench = BenchReport::from_trials(trials);
+                report::print_benchmark(&bench);
+                benchmarks.push((strategy.name(), bench));
+            }
+            for (export, path) in saved {
+                println!("\t{} saved to {}", export.label(), path.display());
+            }
+        }
 
-    println!("\n~~RESOURCE HIERARCHY~~ [with randomness]");
-    run(resource_hierarchy::main, true);
+        if benchmarks.len() > 1 {
+            println!(
+                "\n~~PAIRWISE COMPARISON~~ [{randomness} randomness] ({} on \
+                 total meals)",
+                args.test.name()
+            );
+            let results: Vec<_> = benchmarks
+                .iter()
+                .map(|(name, bench)| (*name, bench))
+                .collect();
+            report::print_comparisons(&results, args.test);
+        }
+    }
 }
 
-fn run<F>(main_f: F, random: bool)
-where
-    F: Send + Fn(Sender<StateMsg>, Arc<AtomicBool>, bool) -> () + 'static,
-{
-    let (tx, rx) = mpsc::channel::<StateMsg>();
-    let kill_switch = Arc::new(AtomicBool::new(false));
-    let cloned_kill_switch = kill_switch.clone();
-    let main_handle = thread::spawn(move || {
-        main_f(tx, cloned_kill_switch, random);
+/// Run `strategy`, drawing the table as it goes if `--visualize` was given,
+/// and keeping every event if `record` is set.
+fn run_observed(
+    strategy: &dyn Strategy,
+    config: &SimConfig,
+    args: &cli::RunArgs,
+    record: bool,
+) -> (harness::RunReport, Vec<Event>) {
+    let mut events = vec![];
+    let mut table = args
+        .visualize
+        .then(|| LiveTable::new(config.n_philosophers));
+    let mut stdout = io::stdout().lock();
+    if let Some(table) = &mut table {
+        let _ = table.start(&mut stdout);
+    }
+    let report = harness::run_with(strategy, config, |event| {
+        if record {
+            events.push(*event);
+        }
+        if let Some(table) = &mut table {
+            table.update(event);
+            // The drawing is only for show, so a failed write isn't worth
+            // stopping the run for.
+            let _ = table.draw_if_due(&mut stdout);
+        }
     });
-    let start_time = Instant::now();
-    let mut meals_eaten = [0; N_PHILOSOPHERS];
+    if let Some(table) = &mut table {
+        let _ = table.finish(&mut stdout);
+    }
+    let _ = stdout.flush();
+    (report, events)
+}
 
-    while start_time.elapsed().as_secs() < RUN_TIME_SECONDS {
-        match rx.try_recv() {
-            Ok(msg) => match msg {
-                StateMsg {
-                    id,
-                    state: PhilosopherState::Eating,
-                } => {
-                    meals_eaten[id - 1] += 1;
-                }
-                StateMsg {
-                    id,
-                    state: PhilosopherState::Dead,
-                } => {
-                    println!("Philosopher {id} has died from starvation!");
-                    return;
-                }
-                _ => {}
-            },
-            Err(TryRecvError::Disconnected) => {
-                println!("Oh no!");
-                return;
-            }
-            Err(TryRecvError::Empty) => {}
+/// Count the meals in a saved trace as if it was being run again, drawing
+/// the table as it goes if `--visualize` was given.
+fn replay(registry: &Registry, args: &cli::ReplayArgs) {
+    let (header, events) = match trace::load(&args.trace) {
+        Ok(trace) => trace,
+        Err(e) => {
+            eprintln!("Couldn't read {}: {e}", args.trace.display());
+            std::process::exit(1);
         }
-    }
+    };
+    let title = registry
+        .get(&header.strategy)
+        .map_or_else(|| header.strategy.to_uppercase(), |s| s.title());
+    let randomness = if header.config.random { "with" } else { "no" };
+    println!(
+        "~~{title}~~ [{randomness} randomness] (replayed from {})",
+        args.trace.display()
+    );
 
-    kill_switch.store(true, Ordering::Relaxed);
-    main_handle.join().unwrap();
-    println!("\tTotal meals eaten: {}", meals_eaten.iter().sum::<i32>());
-    for (i, n) in meals_eaten.iter().enumerate() {
-        println!("\tPhilosopher {}: {n} meals", i + 1);
+    let mut table = args
+        .visualize
+        .then(|| LiveTable::new(header.config.n_philosophers));
+    let mut stdout = io::stdout().lock();
+    if let Some(table) = &mut table {
+        let _ = table.start(&mut stdout);
     }
+    let started = Instant::now();
+    let report =
+        harness::replay(&header.config, &events, &header.end, |event| {
+            if let Some(table) = &mut table {
+                // Hold each event back until it's due at the chosen speed.
+                let due = event.at.div_f64(args.speed);
+                thread::sleep(due.saturating_sub(started.elapsed()));
+                table.update(event);
+                let _ = table.draw_if_due(&mut stdout);
+            }
+        });
+    if let Some(table) = &mut table {
+        // Carry on until the end of the run, like the run itself did.
+        thread::sleep(
+            report
+                .elapsed
+                .div_f64(args.speed)
+                .saturating_sub(started.elapsed()),
+        );
+        let _ = table.finish(&mut stdout);
+    }
+    let _ = stdout.flush();
+    report::print_run(&report);
 }
 
-pub struct StateMsg {
-    pub id: usize,
-    pub state: PhilosopherState,
-}
-
-#[derive(Eq, PartialEq, Copy, Clone)]
-pub enum PhilosopherState {
-    Eating,
-    Hungry(Instant),
-    Thinking,
-    Dead,
+/// Ways of saving a run's events to a file.
+#[derive(Clone, Copy)]
+enum Export {
+    Trace,
+    ChromeTrace,
+    /// A Gantt chart, zoomed in to the window if there is one.
+    Svg(Option<Window>),
 }
 
-pub trait Diner {
-    /// Begin the cycle of thinking and eating. Only ends if the philosopher
-    /// starves to death or if the killswitch is activated.
-    fn run(&mut self) {
-        while self.current_state() != PhilosopherState::Dead
-            && !self.is_kill_switch_active()
-        {
-            self.think();
-            self.eat();
+impl Export {
+    fn label(self) -> &'static str {
+        match self {
+            Export::Trace => "Trace",
+            Export::ChromeTrace => "Chrome trace",
+            Export::Svg(_) => "Gantt chart",
         }
     }
 
-    fn send_state(&self);
-
-    fn think(&mut self);
-
-    fn eat(&mut self);
-
-    fn current_state(&self) -> PhilosopherState;
-
-    fn is_kill_switch_active(&self) -> bool;
-
-    /// Has the philosopher been hungry for longer than the maximum time?
-    fn has_starved_to_death(&self) -> bool {
-        if let PhilosopherState::Hungry(hungry_since) = self.current_state() {
-            if hungry_since.elapsed().as_millis() > HUNGER_THRESHOLD_MILLIS {
-                true
-            } else {
-                false
-            }
-        } else {
-            false
+    fn save(self, path: &Path, header: &TraceHeader, events: &[Event]) {
+        let result = match self {
+            Export::Trace => trace::save(path, header, events),
+            Export::ChromeTrace => chrome_trace::save(path, header, events),
+            Export::Svg(window) => svg::save(path, header, events, window),
+        };
+        if let Err(e) = result {
+            eprintln!(
+                "Couldn't save {} to {}: {e}",
+                self.label().to_lowercase(),
+                path.display()
+            );
+            std::process::exit(1);
         }
     }
+}
 
-    /// Eat or think for a random amount of time.
-    fn sleep(&self, random: bool) {
-        thread::sleep(Self::generate_duration(random));
+/// Where to save an export when there's more than one run, e.g.
+/// `trace.two_forks.random.3.jsonl` for the third trial of `two_forks` with
+/// random timing.
+fn export_path(
+    base: &Path,
+    strategy: &dyn Strategy,
+    random: bool,
+    trial: usize,
+    args: &cli::RunArgs,
+) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let timing = if random { "random" } else { "fixed" };
+    let mut name = format!("{stem}.{}.{timing}", strategy.name());
+    if args.trials > 1 {
+        name.push_str(&format!(".{}", trial + 1));
+    }
+    if let Some(extension) = base.extension() {
+        name.push_str(&format!(".{}", extension.to_string_lossy()));
     }
+    base.with_file_name(name)
+}
 
-    fn generate_duration(random: bool) -> Duration {
-        let millis: u64 = if random {
-            thread_rng().gen_range(MIN_DURATION_MILLIS..MAX_DURATION_MILLIS)
-        } else {
-            MAX_DURATION_MILLIS
-        };
-        Duration::from_millis(millis)
+fn list_strategies(registry: &Registry) {
+    println!(
+        "{:<20}{:<10}{:<12}{:<13}DESCRIPTION",
+        "NAME", "DEADLOCK", "STARVATION", "CENTRALISED"
+    );
+    for strategy in registry.iter() {
+        let Properties {
+            deadlock_free,
+            starvation_free,
+            centralised,
+        } = strategy.properties();
+        let yes_no = |b: bool| if b { "free" } else { "possible" };
+        println!(
+            "{:<20}{:<10}{:<12}{:<13}{}",
+            strategy.name(),
+            yes_no(deadlock_free),
+            yes_no(starvation_free),
+            if centralised { "yes" } else { "no" },
+            strategy.description()
+        );
     }
 }
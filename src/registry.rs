@@ -0,0 +1,146 @@
+use crate::{
+    arbitrator, break_symmetry, chandy_misra, resource_hierarchy, semaphores,
+    sequential, two_forks, Strategy,
+};
+use std::error::Error;
+use std::fmt;
+
+/// What a strategy guarantees, so strategies can be grouped and compared
+/// without having to read their code.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Properties {
+    /// The philosophers can never all end up waiting on each other.
+    pub deadlock_free: bool,
+    /// Every hungry philosopher is guaranteed to eat eventually.
+    pub starvation_free: bool,
+    /// A single thread decides who gets to eat.
+    pub centralised: bool,
+}
+
+/// A collection of strategies, looked up by name.
+///
+/// Strategies are kept in the order they were registered, which is the order
+/// they're run and reported in.
+#[derive(Default)]
+pub struct Registry {
+    strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Registry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry containing every strategy in this crate.
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(sequential::Sequential).unwrap();
+        registry.register(two_forks::TwoForks).unwrap();
+        registry.register(break_symmetry::BreakSymmetry).unwrap();
+        registry.register(semaphores::Semaphores).unwrap();
+        registry.register(chandy_misra::ChandyMisra).unwrap();
+        registry.register(arbitrator::Arbitrator).unwrap();
+        registry
+            .register(resource_hierarchy::ResourceHierarchy)
+            .unwrap();
+        registry
+    }
+
+    /// Add a strategy. Names have to be unique.
+    pub fn register<S>(&mut self, strategy: S) -> Result<(), RegistryError>
+    where
+        S: Strategy + 'static,
+    {
+        if self.get(strategy.name()).is_some() {
+            return Err(RegistryError::DuplicateName(strategy.name()));
+        }
+        self.strategies.push(Box::new(strategy));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Strategy> {
+        self.iter().find(|strategy| strategy.name() == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Strategy> {
+        self.strategies.iter().map(|strategy| strategy.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.strategies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strategies.is_empty()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RegistryError {
+    /// A strategy with this name has already been registered.
+    DuplicateName(&'static str),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::DuplicateName(name) => {
+                write!(f, "A strategy called '{name}' is already registered")
+            }
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Clock, EventSink, SimConfig};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    /// A strategy that doesn't seat anyone.
+    struct Empty(&'static str);
+
+    impl Strategy for Empty {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn description(&self) -> &'static str {
+            "Nobody comes to dinner"
+        }
+
+        fn properties(&self) -> Properties {
+            Properties::default()
+        }
+
+        fn run(
+            &self,
+            _: &SimConfig,
+            _: Clock,
+            _: EventSink,
+            _: Arc<AtomicBool>,
+        ) {
+        }
+    }
+
+    #[test]
+    fn strategies_are_looked_up_by_unique_names() {
+        let mut registry = Registry::builtin();
+        let builtin = registry.len();
+        registry.register(Empty("empty")).unwrap();
+        assert_eq!(registry.len(), builtin + 1);
+        assert_eq!(registry.get("empty").unwrap().title(), "EMPTY");
+        assert_eq!(registry.iter().last().unwrap().name(), "empty");
+        assert!(registry.get("full").is_none());
+
+        assert_eq!(
+            registry.register(Empty("two_forks")),
+            Err(RegistryError::DuplicateName("two_forks"))
+        );
+        assert_eq!(registry.len(), builtin + 1);
+    }
+}
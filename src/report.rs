@@ -0,0 +1,306 @@
+//! Printing results in the format used by the binary and the README.
+
+use crate::harness::{BenchReport, Outcome, RunReport};
+use crate::histogram::{Histogram, REPORTED_QUANTILES};
+use crate::stats::{Summary, Test, SIGNIFICANCE_LEVEL};
+use std::time::Duration;
+
+/// Print the result of a single run.
+pub fn print_run(report: &RunReport) {
+    match &report.outcome {
+        Outcome::Completed => {
+            println!("\tSeed: {}", report.seed);
+            println!("\tTotal meals eaten: {}", report.total_meals());
+            for (i, n) in report.meals.iter().enumerate() {
+                println!("\tPhilosopher {}: {n} meals", i + 1);
+            }
+            print_fairness(report.jain_index(), report.max_min_ratio());
+            print_failed_attempts(report);
+            print_hunger_waits(&report.hunger_wait_summaries());
+            print_hunger_latency(
+                &report.hunger_histogram(),
+                &report.hunger_histograms,
+            );
+        }
+        Outcome::Starved(id) => {
+            println!("Philosopher {id} has died from starvation!");
+            println!("\tSeed: {}", report.seed);
+        }
+        Outcome::Disconnected => println!("Oh no!"),
+        Outcome::Deadlocked(deadlock) => {
+            println!("Deadlock! Nobody can move:");
+            println!("\t{deadlock}");
+            println!("\tSeed: {}", report.seed);
+        }
+        Outcome::Violated(violation) => {
+            println!("The forks weren't kept apart!");
+            println!("\tSeed: {}", report.seed);
+            for line in violation.to_string().lines() {
+                println!("\t{line}");
+            }
+        }
+    }
+}
+
+/// Print summary statistics for a set of trials.
+pub fn print_benchmark(report: &BenchReport) {
+    if let (Some(first), Some(last)) =
+        (report.trials.first(), report.trials.last())
+    {
+        println!("\tSeeds: {} to {}", first.seed, last.seed);
+    }
+    let n_failed = report.failed_trials().count();
+    if n_failed > 0 {
+        println!(
+            "\t{n_failed} of {} trials ended early and are left out:",
+            report.trials.len()
+        );
+        for trial in report.failed_trials() {
+            match trial.outcome {
+                Outcome::Starved(id) => println!(
+                    "\t\tphilosopher {id} starved after {:.1}s (seed {})",
+                    trial.elapsed.as_secs_f64(),
+                    trial.seed
+                ),
+                Outcome::Deadlocked(_) => println!(
+                    "\t\tdeadlocked after {:.1}s (seed {})",
+                    trial.elapsed.as_secs_f64(),
+                    trial.seed
+                ),
+                Outcome::Violated(_) => println!(
+                    "\t\tthe forks weren't kept apart after {:.1}s (seed {})",
+                    trial.elapsed.as_secs_f64(),
+                    trial.seed
+                ),
+                _ => println!(
+                    "\t\tstrategy stopped after {:.1}s (seed {})",
+                    trial.elapsed.as_secs_f64(),
+                    trial.seed
+                ),
+            }
+        }
+    }
+
+    let Some(total) = &report.total_meals else {
+        println!("\tNo trials ran to completion.");
+        return;
+    };
+    print_summary_header();
+    print_summary_row("Total meals", total, 0);
+    for (i, summary) in report.meals_per_philosopher.iter().enumerate() {
+        if let Some(summary) = summary {
+            print_summary_row(&format!("Philosopher {}", i + 1), summary, 0);
+        }
+    }
+    print_fairness_summaries(report);
+    if let Some(attempts) = &report.failed_attempts_per_meal {
+        // Strategies that wait for their forks never fail to get them.
+        if attempts.max > 0.0 {
+            print_summary_row("Failed/meal", attempts, 1);
+        }
+    }
+    print_hunger_waits(&report.hunger_wait_millis);
+    print_hunger_latency(&report.hunger_histogram(), &report.hunger_histograms);
+}
+
+fn print_fairness(jain_index: Option<f64>, max_min_ratio: Option<f64>) {
+    if let Some(index) = jain_index {
+        println!("\tJain's fairness index: {index:.3}");
+    }
+    match max_min_ratio {
+        Some(ratio) if ratio.is_infinite() => {
+            println!("\tMax/min meal ratio: ∞, somebody ate nothing")
+        }
+        Some(ratio) => println!("\tMax/min meal ratio: {ratio:.2}"),
+        None => {}
+    }
+}
+
+fn print_fairness_summaries(report: &BenchReport) {
+    let describe = |summary: &Summary, decimals: usize| match summary.ci95() {
+        Some((low, high)) => format!(
+            "{:.decimals$} (95% CI [{low:.decimals$}, {high:.decimals$}])",
+            summary.mean
+        ),
+        None => format!("{:.decimals$}", summary.mean),
+    };
+    if let Some(index) = &report.jain_index {
+        println!("\tJain's fairness index: {}", describe(index, 3));
+    }
+    match (&report.max_min_ratio, report.unfed_trials) {
+        (Some(ratio), 0) => {
+            println!("\tMax/min meal ratio: {}", describe(ratio, 2))
+        }
+        (Some(ratio), unfed) => println!(
+            "\tMax/min meal ratio: {}, and ∞ in {unfed} more trials \
+             where somebody ate nothing",
+            describe(ratio, 2)
+        ),
+        (None, 0) => {}
+        (None, _) => {
+            println!("\tMax/min meal ratio: ∞, somebody ate nothing every time")
+        }
+    }
+}
+
+/// Print how often philosophers tried to pick up their forks and failed, and
+/// when they kept at it without anybody eating. Strategies that wait for
+/// their forks instead never fail, so print nothing.
+fn print_failed_attempts(report: &RunReport) {
+    let attempts = &report.failed_attempts;
+    if attempts.total() == 0 {
+        return;
+    }
+    let per_meal = |attempts: u64, meals: u64| {
+        if meals == 0 {
+            "no meals".to_string()
+        } else {
+            format!("{:.1} per meal", attempts as f64 / meals as f64)
+        }
+    };
+    println!(
+        "\tFailed attempts to pick up forks: {} ({})",
+        attempts.total(),
+        per_meal(attempts.total(), report.total_meals())
+    );
+    for (i, (&failed, &meals)) in attempts
+        .by_philosopher
+        .iter()
+        .zip(&report.meals)
+        .enumerate()
+    {
+        println!(
+            "\t\tPhilosopher {}: {failed} ({})",
+            i + 1,
+            per_meal(failed, meals)
+        );
+    }
+    let by_fork: Vec<String> = attempts
+        .by_fork
+        .iter()
+        .enumerate()
+        .map(|(fork, n)| format!("{fork}: {n}"))
+        .collect();
+    println!("\t\tFork already taken: {}", by_fork.join(", "));
+
+    let Some(longest) = report.spin_periods.iter().max_by_key(|p| p.length())
+    else {
+        return;
+    };
+    let spinning: Duration = report
+        .spin_periods
+        .iter()
+        .map(|period| period.length())
+        .sum();
+    println!(
+        "\tSpinning without meals: {} periods, {:.0}ms in all, longest \
+         {:.0}ms from {:.1}s ({} failed attempts)",
+        report.spin_periods.len(),
+        spinning.as_secs_f64() * 1000.0,
+        longest.length().as_secs_f64() * 1000.0,
+        longest.start.as_secs_f64(),
+        longest.attempts
+    );
+}
+
+/// Print how long each philosopher was hungry for before eating.
+fn print_hunger_waits(summaries: &[Option<Summary>]) {
+    if summaries.iter().all(Option::is_none) {
+        return;
+    }
+    println!("\tTime spent hungry before eating (ms):");
+    print_summary_header();
+    for (i, summary) in summaries.iter().enumerate() {
+        if let Some(summary) = summary {
+            print_summary_row(&format!("Philosopher {}", i + 1), summary, 1);
+        }
+    }
+}
+
+/// Print the tail of the hunger latency distribution, over everyone and for
+/// each philosopher.
+fn print_hunger_latency(overall: &Histogram, per_philosopher: &[Histogram]) {
+    if overall.is_empty() {
+        return;
+    }
+    print!("\tHunger latency (ms):  ");
+    for (_, label) in REPORTED_QUANTILES {
+        print!("{label:>9}");
+    }
+    println!("{:>9}", "max");
+    print_latency_row("All philosophers", overall);
+    for (i, histogram) in per_philosopher.iter().enumerate() {
+        if !histogram.is_empty() {
+            print_latency_row(&format!("Philosopher {}", i + 1), histogram);
+        }
+    }
+}
+
+fn print_latency_row(label: &str, histogram: &Histogram) {
+    let millis = |value: Option<Duration>| {
+        value.map_or(f64::NAN, |value| value.as_secs_f64() * 1000.0)
+    };
+    print!("\t{label:<22}");
+    for (quantile, _) in REPORTED_QUANTILES {
+        print!("{:>9.2}", millis(histogram.value_at_quantile(quantile)));
+    }
+    println!("{:>9.2}", millis(histogram.max()));
+}
+
+fn print_summary_header() {
+    println!(
+        "\t{:<16}{:>10}{:>10}{:>10}{:>8}{:>8}   95% CI",
+        "", "mean", "median", "std dev", "min", "max"
+    );
+}
+
+/// Print every pairwise comparison of throughput (total meals) between the
+/// named benchmark results.
+pub fn print_comparisons(results: &[(&str, &BenchReport)], test: Test) {
+    println!(
+        "\t{:<20}{:<20}{:>12}{:>10}",
+        "", "", "difference", "p-value"
+    );
+    for (i, (name_a, a)) in results.iter().enumerate() {
+        for (name_b, b) in &results[i + 1..] {
+            let Some(comparison) = a.compare(b, test) else {
+                println!(
+                    "\t{name_a:<20}{name_b:<20}{:>12}{:>10}   not enough \
+                     completed trials",
+                    "n/a", "n/a"
+                );
+                continue;
+            };
+            let mean = |report: &BenchReport| {
+                report.total_meals.as_ref().map_or(0.0, |s| s.mean)
+            };
+            let difference = mean(a) - mean(b);
+            let verdict = if !comparison.is_significant(SIGNIFICANCE_LEVEL) {
+                "not significant".to_string()
+            } else if difference > 0.0 {
+                format!("{name_a} eats more")
+            } else {
+                format!("{name_b} eats more")
+            };
+            println!(
+                "\t{name_a:<20}{name_b:<20}{difference:>+12.1}{:>10.4}   \
+                 {verdict}",
+                comparison.p_value
+            );
+        }
+    }
+}
+
+/// Print a row of a summary table, with `decimals` places for the min and
+/// max.
+fn print_summary_row(label: &str, summary: &Summary, decimals: usize) {
+    let ci = match summary.ci95() {
+        Some((low, high)) => format!("[{low:.1}, {high:.1}]"),
+        None => "n/a".to_string(),
+    };
+    println!(
+        "\t{label:<16}{:>10.1}{:>10.1}{:>10.1}{:>8.decimals$}{:>8.decimals$}   \
+         {ci}",
+        summary.mean, summary.median, summary.std_dev, summary.min, summary.max
+    );
+}
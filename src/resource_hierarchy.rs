@@ -1,43 +1,31 @@
-#![allow(unused_imports)]
+use crate::clock::JoinHandle;
+use crate::deadlock::Forks;
 use crate::{
-    Diner, PhilosopherState, StateMsg, HUNGER_THRESHOLD_MILLIS,
-    MAX_DURATION_MILLIS, MIN_DURATION_MILLIS, N_PHILOSOPHERS,
+    philosopher_rng, Activity, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
 };
-use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex, MutexGuard};
-use std::thread::{sleep, JoinHandle};
-use std::time::Duration;
-use std::time::Instant;
+use std::sync::Arc;
 
 /// Based on some other guy's solution, works by assigning a strict ordering
 /// hierarchy to the forks. Philosophers will pick up the lowest fork first.
-/// Fork order is based on address in memory.
-pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
-    let forks: Vec<Arc<Mutex<Fork>>> = (0..N_PHILOSOPHERS)
-        .map(|_| Arc::new(Mutex::new(Fork)))
-        .collect();
-
-    let mut philosophers = vec![];
-    for i in 1..N_PHILOSOPHERS + 1 {
-        let left_fork = forks[(i - 1) % N_PHILOSOPHERS].clone();
-        let right_fork = forks[i % N_PHILOSOPHERS].clone();
-        let philosopher = Philosopher::new(
-            i,
-            left_fork.clone(),
-            right_fork.clone(),
-            tx.clone(),
-            kill_switch.clone(),
-            random,
-        );
-        philosophers.push(philosopher);
-    }
+/// A fork's rank is its number on the table, which for every philosopher
+/// apart from the last means the left fork comes first.
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let philosophers =
+        seat_philosophers(clock.clone(), sink, kill_switch, config);
 
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
     for mut philosopher in philosophers {
-        let handle = std::thread::spawn(move || {
+        let handle = clock.spawn(move || {
             philosopher.run();
         });
         handles.push(handle);
@@ -48,87 +36,177 @@ pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
     }
 }
 
-struct Fork;
+/// See [`main`].
+pub struct ResourceHierarchy;
+
+impl Strategy for ResourceHierarchy {
+    fn name(&self) -> &'static str {
+        "resource_hierarchy"
+    }
+
+    fn description(&self) -> &'static str {
+        "Always picks up the lowest ranked fork first"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: false,
+            centralised: false,
+        }
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
+
+fn seat_philosophers(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) -> Vec<Philosopher> {
+    let n_philosophers = config.n_philosophers;
+    let table =
+        Arc::new(Forks::new(n_philosophers, clock.clone(), sink.clone()));
+    let mut philosophers = vec![];
+    for i in 1..n_philosophers + 1 {
+        let philosopher = Philosopher::new(
+            i,
+            (i - 1) % n_philosophers,
+            i % n_philosophers,
+            table.clone(),
+            clock.clone(),
+            sink.clone(),
+            kill_switch.clone(),
+            config.clone(),
+        );
+        philosophers.push(philosopher);
+    }
+    philosophers
+}
 
 struct Philosopher {
     id: usize,
     state: PhilosopherState,
-    left_fork: Arc<Mutex<Fork>>,
-    right_fork: Arc<Mutex<Fork>>,
-    tx: Sender<StateMsg>,
+    left_fork: usize,
+    right_fork: usize,
+    table: Arc<Forks>,
+    clock: Clock,
+    sink: EventSink,
     kill_switch: Arc<AtomicBool>,
-    random: bool,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
 }
 
 impl Philosopher {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: usize,
-        left_fork: Arc<Mutex<Fork>>,
-        right_fork: Arc<Mutex<Fork>>,
-        tx: Sender<StateMsg>,
+        left_fork: usize,
+        right_fork: usize,
+        table: Arc<Forks>,
+        clock: Clock,
+        sink: EventSink,
         kill_switch: Arc<AtomicBool>,
-        random: bool,
+        config: SimConfig,
     ) -> Self {
         Self {
             id,
             state: PhilosopherState::Thinking,
             left_fork,
             right_fork,
-            tx,
+            table,
+            clock,
+            sink,
             kill_switch,
-            random,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            config,
+        }
+    }
+
+    /// The philosopher's forks in the order they should be picked up, lowest
+    /// rank first.
+    fn forks_in_order(&self) -> (usize, usize) {
+        if self.left_fork < self.right_fork {
+            (self.left_fork, self.right_fork)
+        } else {
+            (self.right_fork, self.left_fork)
         }
     }
 }
 
 impl Diner for Philosopher {
-    fn send_state(&self) {
-        self.tx
-            .send(StateMsg {
-                id: self.id,
-                state: self.current_state(),
-            })
-            .expect("Error when sending state.");
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
     }
 
     fn think(&mut self) {
         log::debug!("Philosopher {} is thinking", self.id);
         self.state = PhilosopherState::Thinking;
-        self.sleep(self.random);
+        self.emit(EventKind::StartedThinking);
+        self.sleep(Activity::Thinking);
 
         log::debug!("Philosopher {} is hungry", self.id);
-        self.state = PhilosopherState::Hungry(Instant::now());
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
     }
 
     fn eat(&mut self) {
         while let PhilosopherState::Hungry(_) = self.state {
-            // Pick up left fork first if its address in memory is lower than
-            // the right fork.
-            let (_left, _right): (MutexGuard<Fork>, MutexGuard<Fork>) =
-                if &self.left_fork as *const _ < &self.right_fork as *const _ {
-                    let left = self.left_fork.lock();
-                    let right = self.right_fork.lock();
-                    (left.unwrap(), right.unwrap())
-                } else {
-                    let right = self.right_fork.lock();
-                    let left = self.left_fork.lock();
-                    (left.unwrap(), right.unwrap())
-                };
+            // Pick up the lowest ranked fork first. The guards are assigned
+            // to variables to keep them in scope while eating, and put the
+            // forks down again when they're dropped.
+            let (first, second) = self.forks_in_order();
+            let table = self.table.clone();
+            let kill_switch = self.kill_switch.clone();
+            let Some(_first) = table.pick_up(self.id, first, &kill_switch)
+            else {
+                break;
+            };
+            let Some(_second) = table.pick_up(self.id, second, &kill_switch)
+            else {
+                break;
+            };
             if !self.has_starved_to_death() {
                 // Philosopher has successfully picked up both forks and will
                 // start to eat, as long as they're not dead.
                 log::debug!("Philosopher {} is eating", self.id);
                 self.state = PhilosopherState::Eating;
-                self.sleep(self.random);
-                self.send_state();
+                self.emit(EventKind::StartedEating);
+                self.sleep(Activity::Eating);
+                self.emit(EventKind::FinishedEating);
                 log::debug!("Philosopher {} is full", self.id);
             } else {
                 self.state = PhilosopherState::Dead;
-                self.send_state();
+                self.emit(EventKind::Died);
             }
         }
     }
 
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
     fn current_state(&self) -> PhilosopherState {
         self.state
     }
@@ -138,3 +216,77 @@ impl Diner for Philosopher {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness;
+
+    fn expected_order(id: usize, n_philosophers: usize) -> (usize, usize) {
+        if id == n_philosophers {
+            // The last philosopher's right fork is the first fork on the
+            // table, so they're the only one who goes right first.
+            (0, n_philosophers - 1)
+        } else {
+            (id - 1, id)
+        }
+    }
+
+    #[test]
+    fn philosophers_pick_up_lowest_ranked_fork_first() {
+        for n_philosophers in [2, 5, 7] {
+            let clock = Clock::real();
+            let (sink, _rx) = EventSink::channel(clock.clone(), n_philosophers);
+            let kill_switch = Arc::new(AtomicBool::new(false));
+            let config = SimConfig {
+                n_philosophers,
+                ..SimConfig::default()
+            };
+            let philosophers =
+                seat_philosophers(clock, sink, kill_switch, config);
+
+            for philosopher in &philosophers {
+                assert_eq!(
+                    philosopher.forks_in_order(),
+                    expected_order(philosopher.id, n_philosophers),
+                    "philosopher {} of {n_philosophers} picked up forks in the \
+                     wrong order",
+                    philosopher.id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn forks_are_picked_up_lowest_ranked_first_during_a_run() {
+        let config = SimConfig {
+            run_time_seconds: 1,
+            random: true,
+            virtual_time: true,
+            seed: Some(5),
+            ..SimConfig::default()
+        };
+        let (report, events) =
+            harness::run_recorded(&ResourceHierarchy, &config);
+        let n_philosophers = config.n_philosophers;
+        for id in 1..n_philosophers + 1 {
+            let acquired: Vec<usize> = events
+                .iter()
+                .filter(|event| event.id == id)
+                .filter_map(|event| match event.kind {
+                    EventKind::AcquiredFork(fork) => Some(fork),
+                    _ => None,
+                })
+                .collect();
+            assert!(acquired.len() >= 2, "philosopher {id} never ate");
+            // A run can stop between a philosopher's first and second fork.
+            for pair in acquired.chunks_exact(2) {
+                assert_eq!(
+                    (pair[0], pair[1]),
+                    expected_order(id, n_philosophers),
+                    "philosopher {id}, seed {}",
+                    report.seed
+                );
+            }
+        }
+    }
+}
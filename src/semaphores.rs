@@ -1,11 +1,366 @@
-use crate::StateMsg;
-use std::sync::atomic::AtomicBool;
-use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use crate::clock::JoinHandle;
+use crate::{
+    philosopher_rng, Activity, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
+};
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How long a hungry philosopher blocks on their semaphore before checking
+/// whether they've starved or the kill switch has been activated.
+const WAIT_INTERVAL: Duration = Duration::from_millis(1);
 
 /// Based on Dijkstra's solution, uses binary semaphores so a philosopher knows
 /// whether his neighbours are currently eating, and will only attempt to pick
-/// up the forks when both neighbours are eating.
-pub fn main(tx: Sender<StateMsg>, killswitch: Arc<AtomicBool>, random: bool) {
-    //TODO
+/// up the forks when neither neighbour is eating.
+///
+/// The `mutex` semaphore from Dijkstra's version is a normal `Mutex` here,
+/// because it also has to guard the table of states.
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let n_philosophers = config.n_philosophers;
+    let table = Arc::new(Table::new(n_philosophers));
+
+    let mut philosophers = vec![];
+    for i in 1..n_philosophers + 1 {
+        let philosopher = Philosopher::new(
+            i,
+            table.clone(),
+            clock.clone(),
+            sink.clone(),
+            kill_switch.clone(),
+            config.clone(),
+        );
+        philosophers.push(philosopher);
+    }
+
+    let mut handles: Vec<JoinHandle<()>> = vec![];
+
+    for mut philosopher in philosophers {
+        let handle = clock.spawn(move || {
+            philosopher.run();
+        });
+        handles.push(handle);
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+/// See [`main`].
+pub struct Semaphores;
+
+impl Strategy for Semaphores {
+    fn name(&self) -> &'static str {
+        "semaphores"
+    }
+
+    fn description(&self) -> &'static str {
+        "Only picks up the forks when neither neighbour is eating"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: false,
+            centralised: false,
+        }
+    }
+
+    fn title(&self) -> String {
+        "DIJKSTRA'S SEMAPHORES".to_string()
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
+
+/// A counting semaphore. Dijkstra's solution only ever gives each
+/// philosopher's semaphore zero or one permits, so it behaves as a binary
+/// semaphore.
+struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Take a permit, waiting up to `timeout` for one to be released.
+    /// Returns `false` if no permit was available in time.
+    pub fn acquire_timeout(&self, clock: &Clock, timeout: Duration) -> bool {
+        let mut permits = clock.wait_timeout_while(
+            &self.permits,
+            &self.released,
+            timeout,
+            |permits| *permits == 0,
+        );
+        if *permits > 0 {
+            *permits -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn release(&self) {
+        *self.permits.lock().unwrap() += 1;
+        self.released.notify_one();
+    }
+}
+
+/// The state of a seat as seen by the rest of the table. Unlike
+/// `PhilosopherState` there's no need to know how long someone has been hungry.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+enum SeatState {
+    Thinking,
+    Hungry,
+    Eating,
+}
+
+/// Shared state of the whole table, one entry per philosopher.
+struct Table {
+    states: Mutex<Vec<SeatState>>,
+    semaphores: Vec<Semaphore>,
+}
+
+impl Table {
+    pub fn new(n_philosophers: usize) -> Self {
+        Self {
+            states: Mutex::new(vec![SeatState::Thinking; n_philosophers]),
+            semaphores: (0..n_philosophers)
+                .map(|_| Semaphore::new(0))
+                .collect(),
+        }
+    }
+
+    /// Mark the philosopher as hungry and grant them the forks straight away
+    /// if neither neighbour is eating.
+    pub fn take_forks(&self, id: usize) {
+        let mut states = self.states.lock().unwrap();
+        states[id - 1] = SeatState::Hungry;
+        self.test(&mut states, id - 1);
+    }
+
+    /// Wait for the philosopher's semaphore, which is released once they've
+    /// been granted the forks.
+    pub fn wait_for_forks(
+        &self,
+        clock: &Clock,
+        id: usize,
+        timeout: Duration,
+    ) -> bool {
+        self.semaphores[id - 1].acquire_timeout(clock, timeout)
+    }
+
+    /// Put the forks down, then check whether either neighbour was waiting on
+    /// them and can now eat.
+    pub fn put_forks(&self, id: usize) {
+        let mut states = self.states.lock().unwrap();
+        let n = states.len();
+        states[id - 1] = SeatState::Thinking;
+        self.test(&mut states, (id - 1 + n - 1) % n);
+        self.test(&mut states, id % n);
+    }
+
+    fn test(&self, states: &mut [SeatState], i: usize) {
+        let n = states.len();
+        let left = states[(i + n - 1) % n];
+        let right = states[(i + 1) % n];
+        if states[i] == SeatState::Hungry
+            && left != SeatState::Eating
+            && right != SeatState::Eating
+        {
+            states[i] = SeatState::Eating;
+            self.semaphores[i].release();
+        }
+    }
+}
+
+struct Philosopher {
+    id: usize,
+    state: PhilosopherState,
+    table: Arc<Table>,
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
+}
+
+impl Philosopher {
+    pub fn new(
+        id: usize,
+        table: Arc<Table>,
+        clock: Clock,
+        sink: EventSink,
+        kill_switch: Arc<AtomicBool>,
+        config: SimConfig,
+    ) -> Self {
+        Self {
+            id,
+            state: PhilosopherState::Thinking,
+            table,
+            clock,
+            sink,
+            kill_switch,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            config,
+        }
+    }
+
+    /// The philosopher's left and right forks. The forks are only implied
+    /// by the table of states, but they're numbered for reporting.
+    fn forks(&self) -> [usize; 2] {
+        let n_philosophers = self.config.n_philosophers;
+        [(self.id - 1) % n_philosophers, self.id % n_philosophers]
+    }
+}
+
+impl Diner for Philosopher {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
+    }
+
+    fn think(&mut self) {
+        log::debug!("Philosopher {} is thinking", self.id);
+        self.state = PhilosopherState::Thinking;
+        self.emit(EventKind::StartedThinking);
+        self.sleep(Activity::Thinking);
+
+        log::debug!("Philosopher {} is hungry", self.id);
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
+    }
+
+    fn eat(&mut self) {
+        self.table.take_forks(self.id);
+        while let PhilosopherState::Hungry(_) = self.state {
+            if self
+                .table
+                .wait_for_forks(&self.clock, self.id, WAIT_INTERVAL)
+            {
+                // Neither neighbour was eating so the philosopher has been
+                // given both forks.
+                let forks = self.forks();
+                for fork in forks {
+                    self.emit(EventKind::AcquiredFork(fork));
+                }
+                log::debug!("Philosopher {} is eating", self.id);
+                self.state = PhilosopherState::Eating;
+                self.emit(EventKind::StartedEating);
+                self.sleep(Activity::Eating);
+                self.emit(EventKind::FinishedEating);
+                log::debug!("Philosopher {} is full", self.id);
+                for fork in forks {
+                    self.emit(EventKind::ReleasedFork(fork));
+                }
+                self.table.put_forks(self.id);
+            } else if self.has_starved_to_death() {
+                self.state = PhilosopherState::Dead;
+                self.emit(EventKind::Died);
+            } else if self.is_kill_switch_active() {
+                break;
+            }
+        }
+    }
+
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
+    fn current_state(&self) -> PhilosopherState {
+        self.state
+    }
+
+    fn is_kill_switch_active(&self) -> bool {
+        self.kill_switch.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::harness::{self, Outcome};
+    use crate::invariants::Checker;
+
+    #[test]
+    fn test_never_lets_neighbours_eat_together() {
+        use SeatState::*;
+        let n = 5;
+        let table = Table::new(n);
+        let neighbours_eating = |states: &[SeatState]| {
+            (0..n).any(|i| states[i] == Eating && states[(i + 1) % n] == Eating)
+        };
+        // Every table the philosophers could have left behind, with nobody
+        // eating next to each other yet.
+        for mut code in 0..3usize.pow(n as u32) {
+            let mut states = vec![Thinking; n];
+            for state in &mut states {
+                *state = [Thinking, Hungry, Eating][code % 3];
+                code /= 3;
+            }
+            if neighbours_eating(&states) {
+                continue;
+            }
+            for i in 0..n {
+                let before = states.clone();
+                table.test(&mut states, i);
+                assert!(!neighbours_eating(&states), "{i} in {before:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn everyone_eats_on_a_virtual_clock() {
+        let config = SimConfig {
+            run_time_seconds: 2,
+            random: true,
+            virtual_time: true,
+            seed: Some(3),
+            ..SimConfig::default()
+        };
+        let (report, events) = harness::run_recorded(&Semaphores, &config);
+        assert_eq!(report.outcome, Outcome::Completed);
+        assert!(report.meals.iter().all(|&meals| meals > 0), "{report:?}");
+
+        let mut checker = Checker::new(config.n_philosophers);
+        for event in &events {
+            if let Err(violation) = checker.check(event) {
+                panic!("{violation}");
+            }
+        }
+    }
 }
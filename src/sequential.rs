@@ -1,75 +1,140 @@
-use crate::{Diner, PhilosopherState, StateMsg, N_PHILOSOPHERS};
-use std::rc::Rc;
+use crate::clock::JoinHandle;
+use crate::deadlock::{ForkGuard, Forks};
+use crate::{
+    philosopher_rng, Activity, Backoff, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
+};
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender, SyncSender};
-use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
-use std::time::Instant;
-
-const PHILOSOPHER_ORDER: [usize; N_PHILOSOPHERS] = [1, 3, 5, 2, 4];
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 
 /// Essentially a 'control' to compare other solutions against.
 /// Sequential implementation that just goes around the table in a for loop
 /// telling the philosophers to eat if they're hungry and can pick up both forks.
 ///
 /// To be honest, I found this more difficult than doing it in a "normal" way.
-pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
-    let forks: Vec<Arc<Mutex<Fork>>> = (0..N_PHILOSOPHERS)
-        .map(|_| Arc::new(Mutex::new(Fork)))
-        .collect();
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let n_philosophers = config.n_philosophers;
+    let forks =
+        Arc::new(Forks::new(n_philosophers, clock.clone(), sink.clone()));
 
     let mut philosophers = vec![];
-    let mut philosopher_cmd_txs: Vec<SyncSender<PhilosopherCommand>> = vec![];
+    let mut philosopher_cmd_txs: Vec<Sender<PhilosopherCommand>> = vec![];
+    let mut philosopher_taken_rxs: Vec<Receiver<()>> = vec![];
 
-    for i in 1..N_PHILOSOPHERS + 1 {
-        let left_fork = forks[(i - 1) % N_PHILOSOPHERS].clone();
-        let right_fork = forks[i % N_PHILOSOPHERS].clone();
-        let (cmd_tx, cmd_rx) = std::sync::mpsc::sync_channel(0);
+    for i in 1..n_philosophers + 1 {
+        let (cmd_tx, cmd_rx) = mpsc::channel();
+        let (taken_tx, taken_rx) = mpsc::channel();
         let philosopher = Philosopher::new(
             i,
-            left_fork.clone(),
-            right_fork.clone(),
-            tx.clone(),
+            forks.clone(),
+            (i - 1) % n_philosophers,
+            i % n_philosophers,
+            clock.clone(),
+            sink.clone(),
             kill_switch.clone(),
             cmd_rx,
-            random,
+            taken_tx,
+            config.clone(),
         );
         philosophers.push(philosopher);
         philosopher_cmd_txs.push(cmd_tx);
+        philosopher_taken_rxs.push(taken_rx);
     }
 
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
     for mut philosopher in philosophers {
-        let handle = std::thread::spawn(move || {
+        let handle = clock.spawn(move || {
             philosopher.run();
         });
         handles.push(handle);
     }
 
     // Run the sequential loop until kill_switch is active
+    let order = eating_order(n_philosophers);
     while !kill_switch.load(Ordering::Relaxed) {
-        for i in &PHILOSOPHER_ORDER {
+        for i in &order {
             let cmd_tx = &philosopher_cmd_txs[i - 1];
             if kill_switch.load(Ordering::Relaxed) {
-                cmd_tx.send(PhilosopherCommand::Stop).unwrap();
+                // The philosopher may have already stopped (or starved), in
+                // which case there's nobody to tell.
+                let _ = cmd_tx.send(PhilosopherCommand::Stop);
             } else {
-                // Sync channel has buffer size of 0 so sending blocks while
-                // philosopher is thinking or waiting to eat.
-                match cmd_tx.send(PhilosopherCommand::Eat) {
-                    Ok(_) => {}
-                    Err(_) => break,
+                // Wait for the philosopher to take the command, which they
+                // only do once they've finished thinking. A philosopher that
+                // has stopped can't take it.
+                let taken = cmd_tx.send(PhilosopherCommand::Eat).is_ok()
+                    && clock.recv(&philosopher_taken_rxs[i - 1]).is_ok();
+                if !taken {
+                    break;
                 }
             }
         }
     }
 
+    // Anyone still waiting for a command (e.g. because the loop above gave up
+    // when a philosopher starved) gets woken up by the channel closing.
+    drop(philosopher_cmd_txs);
     for handle in handles {
         handle.join().unwrap();
     }
 }
 
-struct Fork;
+/// See [`main`].
+pub struct Sequential;
+
+impl Strategy for Sequential {
+    fn name(&self) -> &'static str {
+        "sequential"
+    }
+
+    fn description(&self) -> &'static str {
+        "Goes round the table telling each philosopher to eat in turn"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: true,
+            centralised: true,
+        }
+    }
+
+    fn title(&self) -> String {
+        "SEQUENTIAL (CONTROL)".to_string()
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
+
+/// The order to go round the table in: every other philosopher, then the
+/// ones that were skipped (e.g. `[1, 3, 5, 2, 4]` for five). With five or
+/// more at the table, the only neighbours told to eat one after the other are
+/// `n` and 1 when there's an even number of philosophers and the order starts
+/// again. With fewer than five some neighbours can't help being next to each
+/// other (`[1, 3, 2]` for three, say).
+fn eating_order(n_philosophers: usize) -> Vec<usize> {
+    (1..n_philosophers + 1)
+        .step_by(2)
+        .chain((2..n_philosophers + 1).step_by(2))
+        .collect()
+}
 
 enum PhilosopherCommand {
     Eat,
@@ -79,63 +144,78 @@ enum PhilosopherCommand {
 struct Philosopher {
     id: usize,
     state: PhilosopherState,
-    left_fork: Arc<Mutex<Fork>>,
-    right_fork: Arc<Mutex<Fork>>,
-    tx: Sender<StateMsg>,
+    forks: Arc<Forks>,
+    left_fork: usize,
+    right_fork: usize,
+    clock: Clock,
+    sink: EventSink,
     kill_switch: Arc<AtomicBool>,
     cmd_rx: Receiver<PhilosopherCommand>,
-    random: bool,
+    /// Tells the controller a command has been taken.
+    taken_tx: Sender<()>,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
+    backoff: Backoff,
 }
 
 impl Philosopher {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: usize,
-        left_fork: Arc<Mutex<Fork>>,
-        right_fork: Arc<Mutex<Fork>>,
-        tx: Sender<StateMsg>,
+        forks: Arc<Forks>,
+        left_fork: usize,
+        right_fork: usize,
+        clock: Clock,
+        sink: EventSink,
         kill_switch: Arc<AtomicBool>,
         cmd_rx: Receiver<PhilosopherCommand>,
-        random: bool,
+        taken_tx: Sender<()>,
+        config: SimConfig,
     ) -> Self {
         Self {
             id,
             state: PhilosopherState::Thinking,
+            forks,
             left_fork,
             right_fork,
-            tx,
+            clock,
+            sink,
             kill_switch,
             cmd_rx,
-            random,
+            taken_tx,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            backoff: Backoff::new(config.backoff, config.seed, id),
+            config,
         }
     }
 }
 
 impl Diner for Philosopher {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn run(&mut self) {
         self.think();
         while !self.is_kill_switch_active() && !self.has_starved_to_death() {
-            let command = self.cmd_rx.recv().unwrap();
-            match command {
-                PhilosopherCommand::Eat => {
+            match self.clock.recv(&self.cmd_rx) {
+                Ok(PhilosopherCommand::Eat) => {
+                    // The controller only stops listening if it's stopping.
+                    let _ = self.taken_tx.send(());
                     self.eat();
                 }
-                PhilosopherCommand::Stop => break,
+                Ok(PhilosopherCommand::Stop) | Err(_) => break,
             }
         }
 
         if self.has_starved_to_death() {
             self.state = PhilosopherState::Dead;
-            self.send_state();
+            self.emit(EventKind::Died);
         }
     }
 
-    fn send_state(&self) {
-        self.tx
-            .send(StateMsg {
-                id: self.id,
-                state: self.current_state(),
-            })
-            .expect("Error when sending state.");
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
     }
 
     fn think(&mut self) {
@@ -144,10 +224,12 @@ impl Diner for Philosopher {
             _ => {
                 log::debug!("Philosopher {} is thinking", self.id);
                 self.state = PhilosopherState::Thinking;
-                self.sleep(self.random);
+                self.emit(EventKind::StartedThinking);
+                self.sleep(Activity::Thinking);
 
                 log::debug!("Philosopher {} is hungry", self.id);
-                self.state = PhilosopherState::Hungry(Instant::now());
+                self.state = PhilosopherState::Hungry(self.clock.now());
+                self.emit(EventKind::BecameHungry);
             }
         }
     }
@@ -157,17 +239,28 @@ impl Diner for Philosopher {
         match self.state {
             PhilosopherState::Hungry(_) if !self.has_starved_to_death() => {
                 while !eaten {
-                    let pickup_forks =
-                        (self.left_fork.try_lock(), self.right_fork.try_lock());
-                    if let (Ok(_), Ok(_)) = pickup_forks {
+                    let forks = self.forks.clone();
+                    let pickup_forks = (
+                        forks.try_pick_up(self.id, self.left_fork),
+                        forks.try_pick_up(self.id, self.right_fork),
+                    );
+                    if let (Some(_left), Some(_right)) = pickup_forks {
                         // Philosopher has successfully picked up both forks and will
                         // start to eat.
+                        self.backoff.reset();
                         log::debug!("Philosopher {} is eating", self.id);
                         self.state = PhilosopherState::Eating;
-                        self.sleep(self.random);
-                        self.send_state();
+                        self.emit(EventKind::StartedEating);
+                        self.sleep(Activity::Eating);
+                        self.emit(EventKind::FinishedEating);
                         log::debug!("Philosopher {} is full", self.id);
                         eaten = true;
+                    } else {
+                        let (left, right) = pickup_forks;
+                        left.into_iter()
+                            .chain(right)
+                            .for_each(ForkGuard::put_back);
+                        self.backoff.wait(&self.clock);
                     }
                 }
             }
@@ -179,6 +272,18 @@ impl Diner for Philosopher {
         }
     }
 
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
     fn current_state(&self) -> PhilosopherState {
         self.state
     }
@@ -187,3 +292,33 @@ impl Diner for Philosopher {
         self.kill_switch.load(Ordering::Relaxed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_rarely_eat_one_after_the_other() {
+        for n in 2..=7 {
+            let order = eating_order(n);
+            let mut sorted = order.clone();
+            sorted.sort();
+            assert_eq!(sorted, (1..=n).collect::<Vec<_>>());
+
+            // Pairs of neighbours next to each other, going round the order
+            // and back to the start.
+            let neighbours: Vec<(usize, usize)> = (0..n)
+                .map(|i| (order[i], order[(i + 1) % n]))
+                .filter(|&(a, b)| a % n + 1 == b || b % n + 1 == a)
+                .collect();
+            let expected: &[(usize, usize)] = match n {
+                2 => &[(1, 2), (2, 1)],
+                3 => &[(1, 3), (3, 2), (2, 1)],
+                4 => &[(3, 2), (4, 1)],
+                6 => &[(6, 1)],
+                _ => &[],
+            };
+            assert_eq!(neighbours, expected, "{order:?}");
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Summary statistics for results collected over several trials, and tests
+//! for whether two sets of results really differ.
+
+/// Two-sided 95% critical values of Student's t distribution for 1 to 30
+/// degrees of freedom.
+const T_CRITICAL_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// Summary of a set of samples.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation, zero if there's only one sample.
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Half the width of the 95% confidence interval for the mean, using
+    /// Student's t distribution. `None` if there's only one sample.
+    pub ci95_half_width: Option<f64>,
+}
+
+impl Summary {
+    /// Summarise the samples, or `None` if there aren't any.
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let n = samples.len();
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n % 2 == 0 {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        let std_dev = variance(&sorted, mean).sqrt();
+        let ci95_half_width =
+            (n > 1).then(|| t_critical_95(n - 1) * std_dev / (n as f64).sqrt());
+
+        Some(Self {
+            n,
+            mean,
+            median,
+            std_dev,
+            min: sorted[0],
+            max: sorted[n - 1],
+            ci95_half_width,
+        })
+    }
+
+    /// The 95% confidence interval for the mean.
+    pub fn ci95(&self) -> Option<(f64, f64)> {
+        self.ci95_half_width
+            .map(|half_width| (self.mean - half_width, self.mean + half_width))
+    }
+}
+
+/// Jain's fairness index: 1 when every value is the same, down to `1 / n`
+/// when one of them has everything. `None` if there are no values or they're
+/// all zero.
+pub fn jain_index(values: &[f64]) -> Option<f64> {
+    let sum: f64 = values.iter().sum();
+    let sum_of_squares: f64 = values.iter().map(|x| x * x).sum();
+    (sum_of_squares > 0.0)
+        .then(|| sum * sum / (values.len() as f64 * sum_of_squares))
+}
+
+/// The largest value divided by the smallest, infinite if the smallest is
+/// zero. `None` if there are no values or they're all zero, like
+/// [`jain_index`].
+pub fn max_min_ratio(values: &[f64]) -> Option<f64> {
+    let max = values.iter().copied().reduce(f64::max)?;
+    let min = values.iter().copied().reduce(f64::min)?;
+    if max == 0.0 {
+        return None;
+    }
+    Some(if max == min { 1.0 } else { max / min })
+}
+
+/// Sample variance (divides by `n - 1`), zero for a single sample.
+pub(crate) fn variance(samples: &[f64], mean: f64) -> f64 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let sum_of_squares: f64 =
+        samples.iter().map(|sample| (sample - mean).powi(2)).sum();
+    sum_of_squares / (samples.len() - 1) as f64
+}
+
+/// Two-sided 95% critical value of Student's t distribution.
+fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    match degrees_of_freedom {
+        0 => f64::NAN,
+        1..=30 => T_CRITICAL_95[degrees_of_freedom - 1],
+        // Cornish-Fisher expansion around the normal distribution, which is
+        // accurate to three decimal places from here on.
+        df => {
+            let z: f64 = 1.959964;
+            let df = df as f64;
+            z + (z.powi(3) + z) / (4.0 * df)
+                + (5.0 * z.powi(5) + 16.0 * z.powi(3) + 3.0 * z)
+                    / (96.0 * df.powi(2))
+        }
+    }
+}
+
+/// Differences with a p-value at or above this are reported as not
+/// significant.
+pub const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// A test for whether two sets of samples differ by more than chance.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Test {
+    /// Welch's t-test, which compares the means without assuming the two
+    /// sets have the same variance.
+    Welch,
+    /// The Mann-Whitney U test, which only looks at how the samples rank
+    /// against each other so doesn't assume they're normally distributed.
+    MannWhitney,
+}
+
+impl Test {
+    pub fn name(self) -> &'static str {
+        match self {
+            Test::Welch => "Welch's t-test",
+            Test::MannWhitney => "Mann-Whitney U test",
+        }
+    }
+
+    /// Compare two sets of samples, or `None` if there aren't enough
+    /// samples for the test.
+    pub fn compare(self, a: &[f64], b: &[f64]) -> Option<Comparison> {
+        match self {
+            Test::Welch => welch_t_test(a, b),
+            Test::MannWhitney => mann_whitney_u_test(a, b),
+        }
+    }
+}
+
+/// The result of testing whether two sets of samples differ.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub test: Test,
+    /// t for Welch's t-test, U of the first set for Mann-Whitney.
+    pub statistic: f64,
+    /// Two-sided p-value: the chance of a difference at least this big if
+    /// both sets came from the same distribution.
+    pub p_value: f64,
+}
+
+impl Comparison {
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+}
+
+/// Welch's t-test. Needs at least two samples in each set.
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> Option<Comparison> {
+    if a.len() < 2 || b.len() < 2 {
+        return None;
+    }
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let mean_a = a.iter().sum::<f64>() / n_a;
+    let mean_b = b.iter().sum::<f64>() / n_b;
+    let se2_a = variance(a, mean_a) / n_a;
+    let se2_b = variance(b, mean_b) / n_b;
+    let se2 = se2_a + se2_b;
+
+    // With no spread at all the means are either identical or as different
+    // as they could possibly be.
+    if se2 == 0.0 {
+        let p_value = if mean_a == mean_b { 1.0 } else { 0.0 };
+        let statistic = match mean_a.total_cmp(&mean_b) {
+            std::cmp::Ordering::Less => f64::NEG_INFINITY,
+            std::cmp::Ordering::Equal => 0.0,
+            std::cmp::Ordering::Greater => f64::INFINITY,
+        };
+        return Some(Comparison {
+            test: Test::Welch,
+            statistic,
+            p_value,
+        });
+    }
+
+    let t = (mean_a - mean_b) / se2.sqrt();
+    // Welch-Satterthwaite approximation of the degrees of freedom.
+    let df = se2.powi(2)
+        / (se2_a.powi(2) / (n_a - 1.0) + se2_b.powi(2) / (n_b - 1.0));
+    Some(Comparison {
+        test: Test::Welch,
+        statistic: t,
+        p_value: student_t_two_sided_p(t, df),
+    })
+}
+
+/// The Mann-Whitney U test, using the normal approximation with a
+/// correction for ties. The approximation is rough with fewer than about
+/// eight samples in each set, and so is the p-value.
+pub fn mann_whitney_u_test(a: &[f64], b: &[f64]) -> Option<Comparison> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let n = n_a + n_b;
+
+    // Rank everything together, giving tied samples the average of the
+    // ranks they span.
+    let mut pooled: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&x| (x, true))
+        .chain(b.iter().map(|&x| (x, false)))
+        .collect();
+    pooled.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let mut rank_sum_a = 0.0;
+    let mut tie_correction = 0.0;
+    let mut start = 0;
+    while start < pooled.len() {
+        let end = start
+            + pooled[start..]
+                .iter()
+                .take_while(|(x, _)| *x == pooled[start].0)
+                .count();
+        let ties = (end - start) as f64;
+        let rank = (start + end + 1) as f64 / 2.0;
+        rank_sum_a += rank
+            * pooled[start..end].iter().filter(|(_, in_a)| *in_a).count()
+                as f64;
+        tie_correction += ties.powi(3) - ties;
+        start = end;
+    }
+
+    let u = rank_sum_a - n_a * (n_a + 1.0) / 2.0;
+    let mean_u = n_a * n_b / 2.0;
+    let var_u =
+        n_a * n_b / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    let p_value = if var_u <= 0.0 {
+        1.0
+    } else {
+        // Continuity correction, since U only takes whole (or half) values.
+        let z = ((u - mean_u).abs() - 0.5).max(0.0) / var_u.sqrt();
+        erfc(z / std::f64::consts::SQRT_2)
+    };
+    Some(Comparison {
+        test: Test::MannWhitney,
+        statistic: u,
+        p_value: p_value.min(1.0),
+    })
+}
+
+/// Two-sided p-value of `t` under Student's t distribution with `df` degrees
+/// of freedom (which doesn't have to be a whole number).
+fn student_t_two_sided_p(t: f64, df: f64) -> f64 {
+    regularized_incomplete_beta(df / (df + t * t), df / 2.0, 0.5)
+}
+
+/// The regularized incomplete beta function I_x(a, b), evaluated with a
+/// continued fraction (Numerical Recipes, section 6.4).
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b)
+        + a * x.ln()
+        + b * (1.0 - x).ln())
+    .exp();
+    // The continued fraction converges quickly on this side, so use the
+    // symmetry I_x(a, b) = 1 - I_{1-x}(b, a) for the other.
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const EPSILON: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = 1.0 + numerator / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            h *= d * c;
+        }
+        if (d * c - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    h
+}
+
+/// Natural log of the gamma function, using the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Complementary error function, accurate to about 1e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587
+                                    + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} isn't {expected}"
+        );
+    }
+
+    #[test]
+    fn summaries_of_odd_and_even_numbers_of_samples() {
+        let odd = Summary::from_samples(&[3.0, 1.0, 2.0]).unwrap();
+        assert_eq!((odd.n, odd.mean, odd.median), (3, 2.0, 2.0));
+        assert_eq!((odd.min, odd.max, odd.std_dev), (1.0, 3.0, 1.0));
+        // t(2) = 4.303, over the square root of 3.
+        let (low, high) = odd.ci95().unwrap();
+        assert_close(low, 2.0 - 2.4843, 1e-4);
+        assert_close(high, 2.0 + 2.4843, 1e-4);
+
+        let even = Summary::from_samples(&[4.0, 1.0, 3.0, 2.0]).unwrap();
+        assert_eq!((even.mean, even.median), (2.5, 2.5));
+        assert_close(even.std_dev, 1.2910, 1e-4);
+        // t(3) = 3.182, times 1.2910 over 2.
+        assert_close(even.ci95_half_width.unwrap(), 2.0540, 1e-4);
+    }
+
+    #[test]
+    fn a_single_sample_has_no_spread_or_interval() {
+        let one = Summary::from_samples(&[5.0]).unwrap();
+        assert_eq!((one.mean, one.median, one.std_dev), (5.0, 5.0, 0.0));
+        assert_eq!(one.ci95(), None);
+        assert_eq!(Summary::from_samples(&[]), None);
+    }
+
+    #[test]
+    fn t_critical_values_carry_on_past_the_table() {
+        assert_eq!(t_critical_95(1), 12.706);
+        assert_eq!(t_critical_95(30), 2.042);
+        assert!(t_critical_95(0).is_nan());
+        for (df, expected) in [(40, 2.021), (60, 2.000), (120, 1.980)] {
+            assert_close(t_critical_95(df), expected, 1e-3);
+        }
+        assert!(t_critical_95(31) < t_critical_95(30));
+    }
+
+    #[test]
+    fn fairness_of_equal_and_lopsided_meals() {
+        let equal = [7.0; 5];
+        assert_close(jain_index(&equal).unwrap(), 1.0, 1e-12);
+        assert_eq!(max_min_ratio(&equal), Some(1.0));
+
+        // One philosopher gets every meal.
+        let hogged = [10.0, 0.0, 0.0, 0.0, 0.0];
+        assert_close(jain_index(&hogged).unwrap(), 1.0 / 5.0, 1e-12);
+        assert_eq!(max_min_ratio(&hogged), Some(f64::INFINITY));
+
+        // One philosopher starves and everyone else eats the same.
+        let starved = [0.0, 10.0, 10.0, 10.0, 10.0];
+        assert_close(jain_index(&starved).unwrap(), 0.8, 1e-12);
+        assert_eq!(max_min_ratio(&starved), Some(f64::INFINITY));
+
+        // Nobody eating isn't fair or unfair, there's nothing to compare.
+        for nothing in [&[0.0; 5][..], &[]] {
+            assert_eq!(jain_index(nothing), None);
+            assert_eq!(max_min_ratio(nothing), None);
+        }
+    }
+
+    #[test]
+    fn welch_t_test_matches_reference_values() {
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let b = [3.0, 4.0, 5.0, 6.0, 7.0];
+        let result = welch_t_test(&a, &b).unwrap();
+        assert!((result.statistic + 2.0).abs() < 1e-9);
+        assert!((result.p_value - 0.0805).abs() < 1e-4);
+
+        // The 95% critical value should land on p = 0.05.
+        assert!((student_t_two_sided_p(2.228, 10.0) - 0.05).abs() < 1e-4);
+    }
+
+    #[test]
+    fn mann_whitney_u_test_handles_ties_and_separation() {
+        let same = [3.0, 3.0, 3.0, 3.0];
+        assert_eq!(mann_whitney_u_test(&same, &same).unwrap().p_value, 1.0);
+
+        let low: Vec<f64> = (0..10).map(f64::from).collect();
+        let high: Vec<f64> = (10..20).map(f64::from).collect();
+        let result = mann_whitney_u_test(&low, &high).unwrap();
+        assert_eq!(result.statistic, 0.0);
+        assert!(result.is_significant(0.001));
+    }
+}
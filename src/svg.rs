@@ -0,0 +1,316 @@
+//! Drawing a run as a standalone SVG Gantt chart: a row per philosopher
+//! coloured by what they were doing, and a row per fork showing who held it.
+
+use crate::timeline::{Phase, Timeline};
+use crate::trace::TraceHeader;
+use crate::Event;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const WIDTH: f64 = 1200.0;
+const LABEL_WIDTH: f64 = 110.0;
+const RIGHT_MARGIN: f64 = 20.0;
+const TOP: f64 = 50.0;
+const ROW_HEIGHT: f64 = 20.0;
+const ROW_GAP: f64 = 4.0;
+/// Space between the philosophers' rows and the forks' rows.
+const SECTION_GAP: f64 = 16.0;
+const AXIS_HEIGHT: f64 = 30.0;
+const LEGEND_HEIGHT: f64 = 30.0;
+
+const THINKING_COLOUR: &str = "#9ecae1";
+const HUNGRY_COLOUR: &str = "#fdae6b";
+const EATING_COLOUR: &str = "#74c476";
+const DEAD_COLOUR: &str = "#de2d26";
+
+/// The part of a run to draw.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Window {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Window {
+    /// Parse `START:END` in milliseconds, e.g. `1000:1500`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let error = || format!("Expected a window like 1000:1500, got '{s}'");
+        let (start, end) = s.split_once(':').ok_or_else(error)?;
+        let start: u64 = start.trim().parse().map_err(|_| error())?;
+        let end: u64 = end.trim().parse().map_err(|_| error())?;
+        if start >= end {
+            return Err(format!(
+                "The window has to end after it starts, got '{s}'"
+            ));
+        }
+        Ok(Self {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+        })
+    }
+}
+
+/// Draw the run to an SVG file at `path`, zoomed in to `window` if there is
+/// one.
+pub fn save(
+    path: &Path,
+    header: &TraceHeader,
+    events: &[Event],
+    window: Option<Window>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_svg(&mut writer, header, events, window)?;
+    writer.flush()
+}
+
+pub fn write_svg<W: Write>(
+    writer: &mut W,
+    header: &TraceHeader,
+    events: &[Event],
+    window: Option<Window>,
+) -> io::Result<()> {
+    let n_philosophers = header.config.n_philosophers;
+    let timeline = Timeline::from_events(n_philosophers, events);
+    let window = window.unwrap_or(Window {
+        start: Duration::ZERO,
+        end: timeline.end.max(Duration::from_millis(1)),
+    });
+    let chart = Chart { window };
+
+    let row_y = |row: usize| TOP + row as f64 * (ROW_HEIGHT + ROW_GAP);
+    let fork_y = |fork: usize| row_y(n_philosophers + fork) + SECTION_GAP;
+    let rows_bottom = fork_y(n_philosophers);
+    let height = rows_bottom + AXIS_HEIGHT + LEGEND_HEIGHT;
+
+    writeln!(
+        writer,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" \
+         height=\"{height}\" viewBox=\"0 0 {WIDTH} {height}\" \
+         font-family=\"sans-serif\" font-size=\"12\">"
+    )?;
+    writeln!(
+        writer,
+        "<rect width=\"{WIDTH}\" height=\"{height}\" fill=\"white\"/>"
+    )?;
+    let timing = if header.config.random {
+        "with randomness"
+    } else {
+        "no randomness"
+    };
+    writeln!(
+        writer,
+        "<text x=\"{LABEL_WIDTH}\" y=\"24\" font-size=\"16\">{} [{timing}] \
+         {:.0}ms to {:.0}ms</text>",
+        escape_xml(&header.strategy),
+        millis(window.start),
+        millis(window.end),
+    )?;
+    // Anything drawn in the chart area is clipped to the window.
+    writeln!(
+        writer,
+        "<clipPath id=\"chart\"><rect x=\"{LABEL_WIDTH}\" y=\"0\" \
+         width=\"{}\" height=\"{height}\"/></clipPath>",
+        chart.width()
+    )?;
+
+    for (seat, spans) in timeline.philosophers.iter().enumerate() {
+        let y = row_y(seat);
+        label(writer, y, &format!("Philosopher {}", seat + 1))?;
+        writeln!(writer, "<g clip-path=\"url(#chart)\">")?;
+        for span in spans {
+            let colour = match span.phase {
+                Phase::Thinking => THINKING_COLOUR,
+                Phase::Hungry => HUNGRY_COLOUR,
+                Phase::Eating => EATING_COLOUR,
+            };
+            chart.bar(writer, span.start, span.end, y, colour, None)?;
+        }
+        if let Some(died_at) = timeline.deaths[seat] {
+            let end = timeline.end.max(window.end);
+            chart.bar(writer, died_at, end, y, DEAD_COLOUR, Some("dead"))?;
+        }
+        writeln!(writer, "</g>")?;
+    }
+
+    for (fork, holds) in timeline.forks.iter().enumerate() {
+        let y = fork_y(fork);
+        label(writer, y, &format!("Fork {fork}"))?;
+        writeln!(writer, "<g clip-path=\"url(#chart)\">")?;
+        for hold in holds {
+            let holder = hold.holder.to_string();
+            let colour = holder_colour(hold.holder, n_philosophers);
+            chart.bar(
+                writer,
+                hold.start,
+                hold.end,
+                y,
+                &colour,
+                Some(&holder),
+            )?;
+        }
+        writeln!(writer, "</g>")?;
+    }
+
+    chart.axis(writer, rows_bottom)?;
+    legend(writer, rows_bottom + AXIS_HEIGHT)?;
+    writeln!(writer, "</svg>")
+}
+
+struct Chart {
+    window: Window,
+}
+
+impl Chart {
+    fn width(&self) -> f64 {
+        WIDTH - LABEL_WIDTH - RIGHT_MARGIN
+    }
+
+    fn x(&self, at: Duration) -> f64 {
+        let span = millis(self.window.end) - millis(self.window.start);
+        LABEL_WIDTH
+            + (millis(at) - millis(self.window.start)) / span * self.width()
+    }
+
+    /// Draw a bar from `start` to `end`, skipping it if it's outside the
+    /// window. `text` is shown inside the bar if there's room for it.
+    fn bar<W: Write>(
+        &self,
+        writer: &mut W,
+        start: Duration,
+        end: Duration,
+        y: f64,
+        colour: &str,
+        text: Option<&str>,
+    ) -> io::Result<()> {
+        if end < self.window.start || start > self.window.end {
+            return Ok(());
+        }
+        let x = self.x(start);
+        let width = (self.x(end) - x).max(0.5);
+        writeln!(
+            writer,
+            "<rect x=\"{x:.2}\" y=\"{y}\" width=\"{width:.2}\" \
+             height=\"{ROW_HEIGHT}\" fill=\"{colour}\"/>"
+        )?;
+        if let Some(text) = text {
+            // Roughly seven pixels per character at this font size.
+            if width > text.len() as f64 * 7.0 + 4.0 {
+                writeln!(
+                    writer,
+                    "<text x=\"{:.2}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                    x + width / 2.0,
+                    y + ROW_HEIGHT - 6.0,
+                    escape_xml(text)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the time axis along the bottom with a tick every 1, 2 or 5 times
+    /// a power of ten milliseconds, whichever gives about ten of them.
+    fn axis<W: Write>(&self, writer: &mut W, y: f64) -> io::Result<()> {
+        let start = millis(self.window.start);
+        let end = millis(self.window.end);
+        let rough_step = (end - start) / 10.0;
+        let magnitude = 10f64.powf(rough_step.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .iter()
+            .map(|m| m * magnitude)
+            .find(|step| *step >= rough_step)
+            .unwrap_or(magnitude * 10.0);
+
+        writeln!(
+            writer,
+            "<line x1=\"{LABEL_WIDTH}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\" \
+             stroke=\"black\"/>",
+            LABEL_WIDTH + self.width()
+        )?;
+        // Steps below a millisecond need a few decimal places.
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+        let first = (start / step).ceil() as u64;
+        let last = (end / step).floor() as u64;
+        for i in first..=last {
+            let tick = i as f64 * step;
+            let x = self.x(Duration::from_secs_f64(tick / 1000.0));
+            writeln!(
+                writer,
+                "<line x1=\"{x:.2}\" y1=\"{y}\" x2=\"{x:.2}\" y2=\"{}\" \
+                 stroke=\"black\"/><text x=\"{x:.2}\" y=\"{}\" \
+                 text-anchor=\"middle\">{tick:.decimals$}ms</text>",
+                y + 5.0,
+                y + 18.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn label<W: Write>(writer: &mut W, y: f64, text: &str) -> io::Result<()> {
+    writeln!(
+        writer,
+        "<text x=\"8\" y=\"{}\">{}</text>",
+        y + ROW_HEIGHT - 6.0,
+        escape_xml(text)
+    )
+}
+
+fn legend<W: Write>(writer: &mut W, y: f64) -> io::Result<()> {
+    let entries = [
+        ("thinking", THINKING_COLOUR),
+        ("hungry", HUNGRY_COLOUR),
+        ("eating", EATING_COLOUR),
+        ("dead", DEAD_COLOUR),
+    ];
+    for (i, (name, colour)) in entries.iter().enumerate() {
+        let x = LABEL_WIDTH + i as f64 * 100.0;
+        writeln!(
+            writer,
+            "<rect x=\"{x}\" y=\"{y}\" width=\"12\" height=\"12\" \
+             fill=\"{colour}\"/><text x=\"{}\" y=\"{}\">{name}</text>",
+            x + 16.0,
+            y + 11.0
+        )?;
+    }
+    Ok(())
+}
+
+/// A different colour for every philosopher, spread round the colour wheel.
+fn holder_colour(id: usize, n_philosophers: usize) -> String {
+    let hue = (id - 1) as f64 * 360.0 / n_philosophers as f64;
+    format!("hsl({hue:.0}, 55%, 70%)")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::tests::two_meals;
+
+    fn eating_bars(window: Option<Window>) -> usize {
+        let (header, events) = two_meals();
+        let mut out = Vec::new();
+        write_svg(&mut out, &header, &events, window).unwrap();
+        // The legend's swatch is the same colour, but smaller than a bar.
+        let bar = format!("height=\"{ROW_HEIGHT}\" fill=\"{EATING_COLOUR}\"");
+        String::from_utf8(out).unwrap().matches(&bar).count()
+    }
+
+    #[test]
+    fn every_meal_in_the_window_is_a_bar() {
+        assert_eq!(eating_bars(None), 2);
+        assert_eq!(eating_bars(Some(Window::parse("0:16").unwrap())), 1);
+        assert_eq!(eating_bars(Some(Window::parse("31:40").unwrap())), 0);
+    }
+}
@@ -0,0 +1,209 @@
+//! Turning a run's events into spans of time, for drawing what happened.
+
+use crate::{Event, EventKind};
+use std::time::Duration;
+
+/// What a philosopher is doing during a [`Span`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    Thinking,
+    Hungry,
+    Eating,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Thinking => "thinking",
+            Phase::Hungry => "hungry",
+            Phase::Eating => "eating",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub phase: Phase,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// A philosopher holding a fork.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ForkHold {
+    pub holder: usize,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Everything that happened in a run, as spans of time.
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    /// Each philosopher's spans in order, indexed by `id - 1`.
+    pub philosophers: Vec<Vec<Span>>,
+    /// Who held each fork and when, indexed by fork number.
+    pub forks: Vec<Vec<ForkHold>>,
+    /// When each philosopher died, if they did.
+    pub deaths: Vec<Option<Duration>>,
+    /// The time of the last event. Anything still going on at the end is cut
+    /// off here.
+    pub end: Duration,
+}
+
+impl Timeline {
+    pub fn from_events(n_philosophers: usize, events: &[Event]) -> Self {
+        let mut timeline = Timeline {
+            philosophers: vec![vec![]; n_philosophers],
+            forks: vec![vec![]; n_philosophers],
+            deaths: vec![None; n_philosophers],
+            end: events.iter().map(|e| e.at).max().unwrap_or_default(),
+        };
+        let mut current: Vec<Option<(Phase, Duration)>> =
+            vec![None; n_philosophers];
+        let mut fork_holders: Vec<Option<(usize, Duration)>> =
+            vec![None; n_philosophers];
+
+        for event in events {
+            let seat = event.id - 1;
+            let next = match event.kind {
+                EventKind::StartedThinking => Some(Phase::Thinking),
+                EventKind::BecameHungry => Some(Phase::Hungry),
+                EventKind::StartedEating => Some(Phase::Eating),
+                EventKind::FinishedEating | EventKind::Died => None,
+                EventKind::AcquiredFork(fork) => {
+                    fork_holders[fork] = Some((event.id, event.at));
+                    continue;
+                }
+                EventKind::ReleasedFork(fork) => {
+                    if let Some((holder, start)) = fork_holders[fork].take() {
+                        timeline.forks[fork].push(ForkHold {
+                            holder,
+                            start,
+                            end: event.at,
+                        });
+                    }
+                    continue;
+                }
+            };
+            if event.kind == EventKind::Died {
+                timeline.deaths[seat] = Some(event.at);
+            }
+            if let Some((phase, start)) = current[seat].take() {
+                timeline.philosophers[seat].push(Span {
+                    phase,
+                    start,
+                    end: event.at,
+                });
+            }
+            current[seat] = next.map(|phase| (phase, event.at));
+        }
+
+        // Cut off anything that was still going on when the run ended.
+        let end = timeline.end;
+        for (seat, open) in current.into_iter().enumerate() {
+            if let Some((phase, start)) = open {
+                timeline.philosophers[seat].push(Span { phase, start, end });
+            }
+        }
+        for (fork, open) in fork_holders.into_iter().enumerate() {
+            if let Some((holder, start)) = open {
+                timeline.forks[fork].push(ForkHold { holder, start, end });
+            }
+        }
+        timeline
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::harness::RunEnd;
+    use crate::trace::TraceHeader;
+    use crate::SimConfig;
+
+    fn event(millis: u64, id: usize, kind: EventKind) -> Event {
+        Event {
+            at: Duration::from_millis(millis),
+            id,
+            kind,
+        }
+    }
+
+    /// Two philosophers who each eat once, one after the other, for testing
+    /// the things that draw a run.
+    pub(crate) fn two_meals() -> (TraceHeader, Vec<Event>) {
+        let header = TraceHeader {
+            strategy: "two_forks".to_string(),
+            config: SimConfig {
+                n_philosophers: 2,
+                ..SimConfig::default()
+            },
+            seed: None,
+            end: RunEnd {
+                at: Duration::from_millis(40),
+                deadlock: None,
+            },
+        };
+        let events = vec![
+            event(0, 1, EventKind::StartedThinking),
+            event(0, 2, EventKind::StartedThinking),
+            event(5, 1, EventKind::BecameHungry),
+            event(6, 1, EventKind::AcquiredFork(0)),
+            event(6, 1, EventKind::AcquiredFork(1)),
+            event(6, 1, EventKind::StartedEating),
+            event(8, 2, EventKind::BecameHungry),
+            event(16, 1, EventKind::FinishedEating),
+            event(16, 1, EventKind::ReleasedFork(0)),
+            event(16, 1, EventKind::ReleasedFork(1)),
+            event(16, 1, EventKind::StartedThinking),
+            event(17, 2, EventKind::AcquiredFork(1)),
+            event(17, 2, EventKind::AcquiredFork(0)),
+            event(17, 2, EventKind::StartedEating),
+            event(30, 2, EventKind::FinishedEating),
+            event(30, 2, EventKind::ReleasedFork(1)),
+            event(30, 2, EventKind::ReleasedFork(0)),
+            event(30, 2, EventKind::StartedThinking),
+        ];
+        (header, events)
+    }
+
+    #[test]
+    fn events_become_spans_and_fork_holds() {
+        let events = [
+            event(0, 1, EventKind::StartedThinking),
+            event(10, 1, EventKind::BecameHungry),
+            event(12, 1, EventKind::AcquiredFork(0)),
+            event(13, 1, EventKind::AcquiredFork(1)),
+            event(13, 1, EventKind::StartedEating),
+            event(23, 1, EventKind::FinishedEating),
+            event(23, 1, EventKind::ReleasedFork(0)),
+            event(24, 2, EventKind::BecameHungry),
+            event(30, 2, EventKind::Died),
+        ];
+        let timeline = Timeline::from_events(2, &events);
+        let ms = Duration::from_millis;
+
+        let activities: Vec<_> = timeline.philosophers[0]
+            .iter()
+            .map(|span| (span.phase, span.start, span.end))
+            .collect();
+        assert_eq!(
+            activities,
+            [
+                (Phase::Thinking, ms(0), ms(10)),
+                (Phase::Hungry, ms(10), ms(13)),
+                (Phase::Eating, ms(13), ms(23)),
+            ]
+        );
+        assert_eq!(timeline.deaths, [None, Some(ms(30))]);
+        // Fork 1 was never put down, so it's held until the end.
+        assert_eq!(
+            timeline.forks[1],
+            [ForkHold {
+                holder: 1,
+                start: ms(13),
+                end: ms(30)
+            }]
+        );
+    }
+}
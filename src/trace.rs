@@ -0,0 +1,1027 @@
+//! Writing every event from a run to a trace file, and reading it back with
+//! [`load`] so the run can be looked at again later.
+//!
+//! There are two formats. JSON lines is easy to read and to feed into other
+//! tools: the first line is the header and every line after that is an event.
+//!
+//! ```text
+//! {"trace":"dining_philosophers","version":1,"strategy":"two_forks","seed":null,"config":{...},"end_us":10000000,"deadlock":null}
+//! {"at_us":10112,"id":1,"event":"became_hungry"}
+//! {"at_us":10140,"id":1,"event":"acquired_fork","fork":0}
+//! ```
+//!
+//! The binary format is a lot smaller. It starts with [`BINARY_MAGIC`] and the
+//! version, then the header, then the events until the end of the file. Each
+//! event is the change in timestamp since the previous event in microseconds
+//! (a zigzag encoded LEB128 varint, since events from different threads can
+//! arrive slightly out of order), the philosopher id (varint), a byte for the
+//! kind of event and, for fork events, the fork number (varint).
+//!
+//! Only traces of the current [`VERSION`] can be read. A JSON header can
+//! leave out parts of the config, which are left at their defaults.
+
+use crate::deadlock::Deadlock;
+use crate::harness::RunEnd;
+use crate::{
+    Activity, BackoffPolicy, DurationRule, Event, EventKind, SimConfig,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Bumped whenever either format changes.
+pub const VERSION: u64 = 1;
+
+/// The first bytes of a binary trace.
+pub const BINARY_MAGIC: &[u8; 8] = b"DPTRACE\0";
+
+/// Everything needed to know where a trace came from.
+#[derive(Clone, Debug)]
+pub struct TraceHeader {
+    pub strategy: String,
+    pub config: SimConfig,
+    /// The seed the run's randomness came from, if it had one.
+    pub seed: Option<u64>,
+    /// How and when the run ended.
+    pub end: RunEnd,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceFormat {
+    JsonLines,
+    Binary,
+}
+
+impl TraceFormat {
+    /// Work out the format from a file's extension: `.bin` files are binary,
+    /// anything else is JSON lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") => TraceFormat::Binary,
+            _ => TraceFormat::JsonLines,
+        }
+    }
+}
+
+/// Write a trace to `path`, in the format that matches its extension.
+pub fn save(
+    path: &Path,
+    header: &TraceHeader,
+    events: &[Event],
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match TraceFormat::from_path(path) {
+        TraceFormat::JsonLines => {
+            write_json_lines(&mut writer, header, events)?
+        }
+        TraceFormat::Binary => write_binary(&mut writer, header, events)?,
+    }
+    writer.flush()
+}
+
+pub fn write_json_lines<W: Write>(
+    writer: &mut W,
+    header: &TraceHeader,
+    events: &[Event],
+) -> io::Result<()> {
+    let config = &header.config;
+    let seed = header
+        .seed
+        .map_or_else(|| "null".to_string(), |seed| seed.to_string());
+    writeln!(
+        writer,
+        "{{\"trace\":\"dining_philosophers\",\"version\":{VERSION},\
+         \"strategy\":\"{}\",\"seed\":{seed},\"config\":{{\
+         \"n_philosophers\":{},\"max_duration_millis\":{},\
+         \"min_duration_millis\":{},\"hunger_threshold_millis\":{},\
+         \"run_time_seconds\":{},\"random\":{},\"think\":[{}],\"eat\":[{}],\
+         \"virtual_time\":{},\"backoff\":\"{}\"}},\"end_us\":{},\
+         \"deadlock\":{}}}",
+        escape_json(&header.strategy),
+        config.n_philosophers,
+        config.max_duration_millis,
+        config.min_duration_millis,
+        config.hunger_threshold_millis,
+        config.run_time_seconds,
+        config.random,
+        json_rules(config, Activity::Thinking),
+        json_rules(config, Activity::Eating),
+        config.virtual_time,
+        config.backoff,
+        header.end.at.as_micros(),
+        json_deadlock(header.end.deadlock.as_ref()),
+    )?;
+
+    for event in events {
+        write!(
+            writer,
+            "{{\"at_us\":{},\"id\":{},\"event\":\"{}\"",
+            event.at.as_micros(),
+            event.id,
+            kind_name(event.kind)
+        )?;
+        if let Some(fork) = fork(event.kind) {
+            write!(writer, ",\"fork\":{fork}")?;
+        }
+        writeln!(writer, "}}")?;
+    }
+    Ok(())
+}
+
+pub fn write_binary<W: Write>(
+    writer: &mut W,
+    header: &TraceHeader,
+    events: &[Event],
+) -> io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    write_varint(writer, VERSION)?;
+
+    let strategy = header.strategy.as_bytes();
+    write_varint(writer, strategy.len() as u64)?;
+    writer.write_all(strategy)?;
+    match header.seed {
+        Some(seed) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&seed.to_le_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    let config = &header.config;
+    for value in [
+        config.n_philosophers as u64,
+        config.max_duration_millis,
+        config.min_duration_millis,
+        config.hunger_threshold_millis,
+        config.run_time_seconds,
+    ] {
+        write_varint(writer, value)?;
+    }
+    writer.write_all(&[config.random as u8, config.virtual_time as u8])?;
+    for activity in [Activity::Thinking, Activity::Eating] {
+        let rules = rules(config, activity);
+        write_varint(writer, rules.len() as u64)?;
+        for rule in rules {
+            write_varint(writer, rule.len() as u64)?;
+            writer.write_all(rule.as_bytes())?;
+        }
+    }
+    let backoff = config.backoff.to_string();
+    write_varint(writer, backoff.len() as u64)?;
+    writer.write_all(backoff.as_bytes())?;
+    write_varint(writer, header.end.at.as_micros() as u64)?;
+    // The length of the deadlock's cycle, then each philosopher in it and
+    // the fork they're waiting for. No cycle means no deadlock.
+    let deadlock = header.end.deadlock.as_ref();
+    write_varint(writer, deadlock.map_or(0, |d| d.philosophers.len() as u64))?;
+    if let Some(deadlock) = deadlock {
+        for (&id, &fork) in deadlock.philosophers.iter().zip(&deadlock.forks) {
+            write_varint(writer, id as u64)?;
+            write_varint(writer, fork as u64)?;
+        }
+    }
+
+    let mut previous_micros: i128 = 0;
+    for event in events {
+        let micros = event.at.as_micros() as i128;
+        write_varint(writer, zigzag((micros - previous_micros) as i64))?;
+        previous_micros = micros;
+        write_varint(writer, event.id as u64)?;
+        writer.write_all(&[kind_tag(event.kind)])?;
+        if let Some(fork) = fork(event.kind) {
+            write_varint(writer, fork as u64)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a trace written by [`save`]. The format is worked out from the start
+/// of the file rather than its extension.
+pub fn load(path: &Path) -> io::Result<(TraceHeader, Vec<Event>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(BINARY_MAGIC) {
+        read_binary(&mut reader)
+    } else {
+        read_json_lines(&mut reader)
+    }
+}
+
+pub fn read_json_lines<R: BufRead>(
+    reader: &mut R,
+) -> io::Result<(TraceHeader, Vec<Event>)> {
+    let mut header = None;
+    let mut events = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let json = Json::parse(&line)
+            .map_err(|e| invalid(format!("Line {}: {e}", i + 1)))?;
+        match header {
+            None => header = Some(json_header(&json)?),
+            Some(_) => events.push(json_event(&json).ok_or_else(|| {
+                invalid(format!("Line {} isn't an event: {line}", i + 1))
+            })?),
+        }
+    }
+    let header = header.ok_or_else(|| invalid("The trace is empty"))?;
+    check_trace(&header, &events)?;
+    Ok((header, events))
+}
+
+pub fn read_binary<R: Read>(
+    reader: &mut R,
+) -> io::Result<(TraceHeader, Vec<Event>)> {
+    let mut magic = [0; BINARY_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != BINARY_MAGIC {
+        return Err(invalid("Not a binary trace"));
+    }
+    check_version(expect_varint(reader)?)?;
+
+    let strategy = read_string(reader)?;
+    let seed = match read_byte(reader)? {
+        0 => None,
+        _ => {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Some(u64::from_le_bytes(bytes))
+        }
+    };
+    let mut config = SimConfig {
+        n_philosophers: expect_varint(reader)? as usize,
+        max_duration_millis: expect_varint(reader)?,
+        min_duration_millis: expect_varint(reader)?,
+        hunger_threshold_millis: expect_varint(reader)?,
+        run_time_seconds: expect_varint(reader)?,
+        random: read_byte(reader)? != 0,
+        virtual_time: read_byte(reader)? != 0,
+        seed,
+        ..SimConfig::default()
+    };
+    for activity in [Activity::Thinking, Activity::Eating] {
+        for _ in 0..expect_varint(reader)? {
+            let rule = read_string(reader)?;
+            push_rule(&mut config, activity, &rule);
+        }
+    }
+    set_backoff(&mut config, &read_string(reader)?);
+    let mut end = RunEnd {
+        at: Duration::from_micros(expect_varint(reader)?),
+        deadlock: None,
+    };
+    let cycle = expect_varint(reader)?;
+    if cycle > 0 {
+        let mut deadlock = Deadlock {
+            philosophers: vec![],
+            forks: vec![],
+        };
+        for _ in 0..cycle {
+            deadlock.philosophers.push(expect_varint(reader)? as usize);
+            deadlock.forks.push(expect_varint(reader)? as usize);
+        }
+        end.deadlock = Some(deadlock);
+    }
+
+    let mut events = vec![];
+    let mut micros: i128 = 0;
+    // The end of the file can only come where the next event would start.
+    while let Some(delta) = read_varint(reader)? {
+        micros += unzigzag(delta) as i128;
+        let id = expect_varint(reader)? as usize;
+        let tag = read_byte(reader)?;
+        let fork = match tag {
+            2 | 3 => expect_varint(reader)? as usize,
+            _ => 0,
+        };
+        let kind = kind_from_tag(tag, fork)
+            .ok_or_else(|| invalid(format!("Unknown kind of event {tag}")))?;
+        events.push(Event {
+            at: Duration::from_micros(micros.max(0) as u64),
+            id,
+            kind,
+        });
+    }
+    let header = TraceHeader {
+        strategy,
+        config,
+        seed,
+        end,
+    };
+    check_trace(&header, &events)?;
+    Ok((header, events))
+}
+
+fn json_header(json: &Json) -> io::Result<TraceHeader> {
+    if json.get("trace").and_then(Json::as_str) != Some("dining_philosophers") {
+        return Err(invalid("Not a dining philosophers trace"));
+    }
+    check_version(
+        json.get("version")
+            .and_then(Json::as_u64)
+            .ok_or_else(|| invalid("The trace has no version"))?,
+    )?;
+    let strategy = json
+        .get("strategy")
+        .and_then(Json::as_str)
+        .ok_or_else(|| invalid("The trace has no strategy"))?
+        .to_string();
+    let seed = json.get("seed").and_then(Json::as_u64);
+
+    let mut config = SimConfig {
+        seed,
+        ..SimConfig::default()
+    };
+    let fields = json.get("config");
+    let number = |name| fields?.get(name)?.as_u64();
+    let flag = |name| fields?.get(name)?.as_bool();
+    if let Some(n) = number("n_philosophers") {
+        config.n_philosophers = n as usize;
+    }
+    if let Some(n) = number("max_duration_millis") {
+        config.max_duration_millis = n;
+    }
+    if let Some(n) = number("min_duration_millis") {
+        config.min_duration_millis = n;
+    }
+    if let Some(n) = number("hunger_threshold_millis") {
+        config.hunger_threshold_millis = n;
+    }
+    if let Some(n) = number("run_time_seconds") {
+        config.run_time_seconds = n;
+    }
+    if let Some(random) = flag("random") {
+        config.random = random;
+    }
+    if let Some(virtual_time) = flag("virtual_time") {
+        config.virtual_time = virtual_time;
+    }
+    for (name, activity) in
+        [("think", Activity::Thinking), ("eat", Activity::Eating)]
+    {
+        if let Some(Json::Array(rules)) = fields.and_then(|f| f.get(name)) {
+            for rule in rules.iter().filter_map(Json::as_str) {
+                push_rule(&mut config, activity, rule);
+            }
+        }
+    }
+    if let Some(backoff) =
+        fields.and_then(|f| f.get("backoff")).and_then(Json::as_str)
+    {
+        set_backoff(&mut config, backoff);
+    }
+
+    let mut end = RunEnd {
+        at: Duration::from_micros(
+            json.get("end_us").and_then(Json::as_u64).ok_or_else(|| {
+                invalid("The trace doesn't say when it ended")
+            })?,
+        ),
+        deadlock: None,
+    };
+    if let Some(deadlock) = json.get("deadlock").filter(|d| **d != Json::Null) {
+        let numbers = |name| -> io::Result<Vec<usize>> {
+            match deadlock.get(name) {
+                Some(Json::Array(items)) => items
+                    .iter()
+                    .map(|item| item.as_u64().map(|n| n as usize))
+                    .collect::<Option<_>>(),
+                _ => None,
+            }
+            .ok_or_else(|| invalid(format!("The deadlock has no {name}")))
+        };
+        end.deadlock = Some(Deadlock {
+            philosophers: numbers("philosophers")?,
+            forks: numbers("forks")?,
+        });
+    }
+
+    Ok(TraceHeader {
+        strategy,
+        config,
+        seed,
+        end,
+    })
+}
+
+fn json_event(json: &Json) -> Option<Event> {
+    let fork = json
+        .get("fork")
+        .and_then(Json::as_u64)
+        .map(|fork| fork as usize);
+    Some(Event {
+        at: Duration::from_micros(json.get("at_us")?.as_u64()?),
+        id: json.get("id")?.as_u64()? as usize,
+        kind: kind_from_name(json.get("event")?.as_str()?, fork)?,
+    })
+}
+
+/// Make sure the config makes sense and every event is about someone and
+/// something at the table, so nothing reading the trace has to.
+fn check_trace(header: &TraceHeader, events: &[Event]) -> io::Result<()> {
+    header.config.validate().map_err(invalid)?;
+    let n = header.config.n_philosophers;
+    if let Some(deadlock) = &header.end.deadlock {
+        let philosophers = &deadlock.philosophers;
+        if philosophers.len() != deadlock.forks.len()
+            || philosophers.iter().any(|&id| id == 0 || id > n)
+            || deadlock.forks.iter().any(|&fork| fork >= n)
+        {
+            return Err(invalid(format!(
+                "The deadlock ({deadlock}) isn't one at a table of {n}"
+            )));
+        }
+    }
+    if let Some(event) =
+        events.iter().find(|event| event.id == 0 || event.id > n)
+    {
+        return Err(invalid(format!(
+            "There's an event for philosopher {} at a table of {n}",
+            event.id
+        )));
+    }
+    match events
+        .iter()
+        .find_map(|event| fork(event.kind).filter(|&f| f >= n))
+    {
+        Some(fork) => Err(invalid(format!(
+            "There's an event for fork {fork}, but a table of {n} only has \
+             forks 0 to {}",
+            n - 1
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_version(version: u64) -> io::Result<()> {
+    if version != VERSION {
+        return Err(invalid(format!(
+            "The trace is version {version}, but only version {VERSION} can \
+             be read"
+        )));
+    }
+    Ok(())
+}
+
+/// Add a duration rule read from a trace to `config`. The trace is still
+/// worth reading if a rule can't be used any more (say its file of durations
+/// has gone), so it's left out with a warning.
+fn push_rule(config: &mut SimConfig, activity: Activity, rule: &str) {
+    match DurationRule::parse(activity, rule) {
+        Ok(rule) => config.durations.push(rule),
+        Err(e) => log::warn!("Leaving out the duration rule '{rule}': {e}"),
+    }
+}
+
+fn json_deadlock(deadlock: Option<&Deadlock>) -> String {
+    let Some(deadlock) = deadlock else {
+        return "null".to_string();
+    };
+    let list = |numbers: &[usize]| {
+        numbers
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "{{\"philosophers\":[{}],\"forks\":[{}]}}",
+        list(&deadlock.philosophers),
+        list(&deadlock.forks)
+    )
+}
+
+/// Set the backoff policy read from a trace, which like a duration rule only
+/// gets a warning if it can't be read.
+fn set_backoff(config: &mut SimConfig, backoff: &str) {
+    match BackoffPolicy::parse(backoff) {
+        Ok(policy) => config.backoff = policy,
+        Err(e) => log::warn!("Leaving out the backoff '{backoff}': {e}"),
+    }
+}
+
+/// The config's duration rules for `activity`, written as they're given on
+/// the command line.
+fn rules(config: &SimConfig, activity: Activity) -> Vec<String> {
+    config
+        .durations
+        .iter()
+        .filter(|rule| rule.activity == activity)
+        .map(ToString::to_string)
+        .collect()
+}
+
+fn json_rules(config: &SimConfig, activity: Activity) -> String {
+    rules(config, activity)
+        .iter()
+        .map(|rule| format!("\"{}\"", escape_json(rule)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn kind_name(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::StartedThinking => "started_thinking",
+        EventKind::BecameHungry => "became_hungry",
+        EventKind::AcquiredFork(_) => "acquired_fork",
+        EventKind::ReleasedFork(_) => "released_fork",
+        EventKind::StartedEating => "started_eating",
+        EventKind::FinishedEating => "finished_eating",
+        EventKind::Died => "died",
+    }
+}
+
+/// The kind of event called `name`, which for fork events needs the `fork`.
+fn kind_from_name(name: &str, fork: Option<usize>) -> Option<EventKind> {
+    Some(match name {
+        "started_thinking" => EventKind::StartedThinking,
+        "became_hungry" => EventKind::BecameHungry,
+        "acquired_fork" => EventKind::AcquiredFork(fork?),
+        "released_fork" => EventKind::ReleasedFork(fork?),
+        "started_eating" => EventKind::StartedEating,
+        "finished_eating" => EventKind::FinishedEating,
+        "died" => EventKind::Died,
+        _ => return None,
+    })
+}
+
+fn kind_tag(kind: EventKind) -> u8 {
+    match kind {
+        EventKind::StartedThinking => 0,
+        EventKind::BecameHungry => 1,
+        EventKind::AcquiredFork(_) => 2,
+        EventKind::ReleasedFork(_) => 3,
+        EventKind::StartedEating => 4,
+        EventKind::FinishedEating => 5,
+        EventKind::Died => 6,
+    }
+}
+
+fn kind_from_tag(tag: u8, fork: usize) -> Option<EventKind> {
+    Some(match tag {
+        0 => EventKind::StartedThinking,
+        1 => EventKind::BecameHungry,
+        2 => EventKind::AcquiredFork(fork),
+        3 => EventKind::ReleasedFork(fork),
+        4 => EventKind::StartedEating,
+        5 => EventKind::FinishedEating,
+        6 => EventKind::Died,
+        _ => return None,
+    })
+}
+
+fn fork(kind: EventKind) -> Option<usize> {
+    match kind {
+        EventKind::AcquiredFork(fork) | EventKind::ReleasedFork(fork) => {
+            Some(fork)
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Map signed integers to unsigned ones so small negative numbers stay small.
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+/// Unsigned LEB128: seven bits at a time, lowest first, with the top bit set
+/// on every byte but the last.
+fn write_varint<W: Write>(writer: &mut W, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Read a varint, or `None` if the reader was already at the end.
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        if reader.read(&mut byte)? == 0 {
+            return match shift {
+                0 => Ok(None),
+                _ => Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+        }
+        n |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+    }
+    Err(invalid("A varint is too long"))
+}
+
+fn expect_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    read_varint(reader)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = expect_varint(reader)?;
+    let mut bytes = vec![];
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("A string isn't UTF-8"))
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Just enough JSON to read traces back.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    /// Kept as written, so 64-bit seeds don't get rounded.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(s: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            bytes: s.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(parser.error("Expected the end of the line")),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.peek()
+                {
+                    self.pos += 1;
+                }
+                let number = &self.bytes[start..self.pos];
+                Ok(Json::Number(String::from_utf8_lossy(number).into_owned()))
+            }
+            _ => {
+                for (word, value) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("Expected a value"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            fields.push((name, self.value()?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',') => {}
+                Some(b'}') => return Ok(Json::Object(fields)),
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(b',') => {}
+                Some(b']') => return Ok(Json::Array(values)),
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            match self.next() {
+                Some(b'"') => break,
+                Some(b'\\') => match self.next() {
+                    Some(b'u') => {
+                        let hex = self
+                            .bytes
+                            .get(self.pos..self.pos + 4)
+                            .and_then(|hex| std::str::from_utf8(hex).ok())
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .ok_or_else(|| self.error("Bad \\u escape"))?;
+                        self.pos += 4;
+                        let c = char::from_u32(hex).unwrap_or('\u{fffd}');
+                        bytes.extend(c.to_string().as_bytes());
+                    }
+                    Some(c) => bytes.push(match c {
+                        b'n' => b'\n',
+                        b't' => b'\t',
+                        b'r' => b'\r',
+                        b'b' => 0x08,
+                        b'f' => 0x0c,
+                        c => c,
+                    }),
+                    None => return Err(self.error("Unfinished string")),
+                },
+                Some(byte) => bytes.push(byte),
+                None => return Err(self.error("Unfinished string")),
+            }
+        }
+        // The bytes came from a `str` and escapes are whole characters.
+        String::from_utf8(bytes).map_err(|_| self.error("Bad string"))
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        match self.next() {
+            Some(b) if b == byte => Ok(()),
+            _ => Err(self.error(&format!("Expected '{}'", byte as char))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at column {}", self.pos + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DurationDistribution;
+
+    fn sample() -> (TraceHeader, Vec<Event>) {
+        let config = SimConfig {
+            n_philosophers: 3,
+            random: true,
+            virtual_time: true,
+            durations: vec![
+                DurationRule::parse(Activity::Thinking, "exp:5").unwrap(),
+                DurationRule::parse(Activity::Eating, "2=lognormal:3,0.5")
+                    .unwrap(),
+            ],
+            seed: Some(u64::MAX - 1),
+            backoff: BackoffPolicy::parse("exp:0.5,8").unwrap(),
+            ..SimConfig::default()
+        };
+        let header = TraceHeader {
+            strategy: "two_forks".to_string(),
+            config,
+            seed: Some(u64::MAX - 1),
+            end: RunEnd {
+                at: Duration::from_millis(5_250),
+                deadlock: Some(Deadlock {
+                    philosophers: vec![3, 1],
+                    forks: vec![2, 0],
+                }),
+            },
+        };
+        let at = Duration::from_micros;
+        let events = vec![
+            Event {
+                at: at(10),
+                id: 1,
+                kind: EventKind::BecameHungry,
+            },
+            Event {
+                at: at(12),
+                id: 1,
+                kind: EventKind::AcquiredFork(0),
+            },
+            // Events can arrive slightly out of order.
+            Event {
+                at: at(11),
+                id: 3,
+                kind: EventKind::ReleasedFork(2),
+            },
+            Event {
+                at: at(5_000_000),
+                id: 2,
+                kind: EventKind::Died,
+            },
+        ];
+        (header, events)
+    }
+
+    fn assert_same(header: &TraceHeader, read: &TraceHeader) {
+        assert_eq!(read.strategy, header.strategy);
+        assert_eq!(read.seed, header.seed);
+        assert_eq!(read.config.n_philosophers, header.config.n_philosophers);
+        assert_eq!(read.config.random, header.config.random);
+        assert_eq!(read.config.virtual_time, header.config.virtual_time);
+        assert_eq!(read.config.durations, header.config.durations);
+        assert_eq!(read.config.backoff, header.config.backoff);
+        assert_eq!(read.end, header.end);
+        assert!(matches!(
+            read.config.durations[1].distribution,
+            DurationDistribution::LogNormal { .. }
+        ));
+    }
+
+    #[test]
+    fn json_lines_traces_read_back_the_same() {
+        let (header, events) = sample();
+        let mut bytes = vec![];
+        write_json_lines(&mut bytes, &header, &events).unwrap();
+        let (read_header, read_events) =
+            read_json_lines(&mut bytes.as_slice()).unwrap();
+        assert_same(&header, &read_header);
+        assert_eq!(read_events, events);
+    }
+
+    #[test]
+    fn binary_traces_read_back_the_same() {
+        let (header, events) = sample();
+        let mut bytes = vec![];
+        write_binary(&mut bytes, &header, &events).unwrap();
+        let (read_header, read_events) =
+            read_binary(&mut bytes.as_slice()).unwrap();
+        assert_same(&header, &read_header);
+        assert_eq!(read_events, events);
+
+        // Cutting an event short is an error rather than the end.
+        bytes.pop();
+        assert!(read_binary(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn malformed_traces_are_rejected() {
+        let header = format!(
+            "{{\"trace\":\"dining_philosophers\",\"version\":{VERSION},\
+             \"strategy\":\"two_forks\",\"seed\":null,\
+             \"config\":{{\"n_philosophers\":5}},\"end_us\":10,\
+             \"deadlock\":null}}"
+        );
+        let read = |lines: &[&str]| {
+            let trace = format!("{header}\n{}\n", lines.join("\n"));
+            read_json_lines(&mut trace.as_bytes())
+        };
+        let error = |lines: &[&str]| read(lines).unwrap_err().to_string();
+
+        assert!(read(&[
+            r#"{"at_us":1,"id":1,"event":"acquired_fork","fork":4}"#
+        ])
+        .is_ok());
+        assert_eq!(
+            error(&[r#"{"at_us":1,"id":1,"event":"acquired_fork","fork":99}"#]),
+            "There's an event for fork 99, but a table of 5 only has forks 0 \
+             to 4"
+        );
+        assert!(error(&[r#"{"at_us":1,"id":1,"event":"released_fork"}"#])
+            .starts_with("Line 2 isn't an event"));
+        assert_eq!(
+            error(&[r#"{"at_us":1,"id":6,"event":"died"}"#]),
+            "There's an event for philosopher 6 at a table of 5"
+        );
+
+        let newer = header.replace(
+            &format!("\"version\":{VERSION}"),
+            &format!("\"version\":{}", VERSION + 1),
+        );
+        assert!(read_json_lines(&mut newer.as_bytes())
+            .unwrap_err()
+            .to_string()
+            .starts_with(&format!("The trace is version {}", VERSION + 1)));
+
+        let lonely =
+            header.replace("\"n_philosophers\":5", "\"n_philosophers\":1");
+        assert_eq!(
+            read_json_lines(&mut lonely.as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "There must be at least two philosophers"
+        );
+    }
+
+    #[test]
+    fn json_strings_are_unescaped() {
+        let json =
+            Json::parse(r#"{"a":"x\"y\\z\u0001","b":[1,true,null]}"#).unwrap();
+        assert_eq!(json.get("a").and_then(Json::as_str), Some("x\"y\\z\u{1}"));
+        assert_eq!(
+            json.get("b"),
+            Some(&Json::Array(vec![
+                Json::Number("1".to_string()),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+    }
+}
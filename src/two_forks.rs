@@ -1,35 +1,38 @@
-#![allow(unused_imports)]
+use crate::clock::JoinHandle;
+use crate::deadlock::{ForkGuard, Forks};
 use crate::{
-    Diner, PhilosopherState, StateMsg, HUNGER_THRESHOLD_MILLIS,
-    MAX_DURATION_MILLIS, MIN_DURATION_MILLIS, N_PHILOSOPHERS,
+    philosopher_rng, Activity, Backoff, Clock, Diner, EventKind, EventSink,
+    PhilosopherState, Properties, SimConfig, Strategy,
 };
-use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::cell::{RefCell, RefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex};
-use std::thread::{sleep, JoinHandle};
-use std::time::Duration;
-use std::time::Instant;
+use std::sync::Arc;
 
 /// This one is my solution. The philosophers attempt to pick up both forks,
 /// and if they're unable to pick up both they drop any fork they did manage
-/// to pick up.
-pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
-    let forks: Vec<Arc<Mutex<Fork>>> = (0..N_PHILOSOPHERS)
-        .map(|_| Arc::new(Mutex::new(Fork)))
-        .collect();
+/// to pick up, then back off with [`SimConfig::backoff`] before trying again.
+pub fn main(
+    clock: Clock,
+    sink: EventSink,
+    kill_switch: Arc<AtomicBool>,
+    config: SimConfig,
+) {
+    let n_philosophers = config.n_philosophers;
+    let forks =
+        Arc::new(Forks::new(n_philosophers, clock.clone(), sink.clone()));
 
     let mut philosophers = vec![];
-    for i in 1..N_PHILOSOPHERS + 1 {
-        let left_fork = forks[(i - 1) % N_PHILOSOPHERS].clone();
-        let right_fork = forks[i % N_PHILOSOPHERS].clone();
+    for i in 1..n_philosophers + 1 {
         let philosopher = Philosopher::new(
             i,
-            left_fork.clone(),
-            right_fork.clone(),
-            tx.clone(),
+            forks.clone(),
+            (i - 1) % n_philosophers,
+            i % n_philosophers,
+            clock.clone(),
+            sink.clone(),
             kill_switch.clone(),
-            random,
+            config.clone(),
         );
         philosophers.push(philosopher);
     }
@@ -37,7 +40,7 @@ pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
     for mut philosopher in philosophers {
-        let handle = std::thread::spawn(move || {
+        let handle = clock.spawn(move || {
             philosopher.run();
         });
         handles.push(handle);
@@ -48,80 +51,146 @@ pub fn main(tx: Sender<StateMsg>, kill_switch: Arc<AtomicBool>, random: bool) {
     }
 }
 
-struct Fork;
+/// See [`main`].
+pub struct TwoForks;
+
+impl Strategy for TwoForks {
+    fn name(&self) -> &'static str {
+        "two_forks"
+    }
+
+    fn description(&self) -> &'static str {
+        "Tries both forks, putting one back if the other is taken"
+    }
+
+    fn properties(&self) -> Properties {
+        Properties {
+            deadlock_free: true,
+            starvation_free: false,
+            centralised: false,
+        }
+    }
+
+    fn run(
+        &self,
+        config: &SimConfig,
+        clock: Clock,
+        sink: EventSink,
+        cancel: Arc<AtomicBool>,
+    ) {
+        main(clock, sink, cancel, config.clone());
+    }
+}
 
 struct Philosopher {
     id: usize,
     state: PhilosopherState,
-    left_fork: Arc<Mutex<Fork>>,
-    right_fork: Arc<Mutex<Fork>>,
-    tx: Sender<StateMsg>,
+    forks: Arc<Forks>,
+    left_fork: usize,
+    right_fork: usize,
+    clock: Clock,
+    sink: EventSink,
     kill_switch: Arc<AtomicBool>,
-    random: bool,
+    config: SimConfig,
+    rng: RefCell<StdRng>,
+    backoff: Backoff,
 }
 
 impl Philosopher {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: usize,
-        left_fork: Arc<Mutex<Fork>>,
-        right_fork: Arc<Mutex<Fork>>,
-        tx: Sender<StateMsg>,
+        forks: Arc<Forks>,
+        left_fork: usize,
+        right_fork: usize,
+        clock: Clock,
+        sink: EventSink,
         kill_switch: Arc<AtomicBool>,
-        random: bool,
+        config: SimConfig,
     ) -> Self {
         Self {
             id,
             state: PhilosopherState::Thinking,
+            forks,
             left_fork,
             right_fork,
-            tx,
+            clock,
+            sink,
             kill_switch,
-            random,
+            rng: RefCell::new(philosopher_rng(config.seed, id)),
+            backoff: Backoff::new(config.backoff, config.seed, id),
+            config,
         }
     }
 }
 
 impl Diner for Philosopher {
-    fn send_state(&self) {
-        self.tx
-            .send(StateMsg {
-                id: self.id,
-                state: self.current_state(),
-            })
-            .expect("Error when sending state.");
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn emit(&self, kind: EventKind) {
+        self.sink.emit(self.id, kind);
     }
 
     fn think(&mut self) {
         log::debug!("Philosopher {} is thinking", self.id);
         self.state = PhilosopherState::Thinking;
-        self.sleep(self.random);
+        self.emit(EventKind::StartedThinking);
+        self.sleep(Activity::Thinking);
 
         log::debug!("Philosopher {} is hungry", self.id);
-        self.state = PhilosopherState::Hungry(Instant::now());
+        self.state = PhilosopherState::Hungry(self.clock.now());
+        self.emit(EventKind::BecameHungry);
     }
 
     fn eat(&mut self) {
         while let PhilosopherState::Hungry(_) = self.state {
-            // Attempt to pick up both forks at the same time
-            let pickup_forks =
-                (self.left_fork.try_lock(), self.right_fork.try_lock());
-            if let (Ok(_), Ok(_)) = pickup_forks {
+            // Attempt to pick up both forks at the same time. They're put
+            // down again when the guards are dropped.
+            let forks = self.forks.clone();
+            let pickup_forks = (
+                forks.try_pick_up(self.id, self.left_fork),
+                forks.try_pick_up(self.id, self.right_fork),
+            );
+            if let (Some(_left), Some(_right)) = pickup_forks {
                 // Philosopher has successfully picked up both forks and will
                 // start to eat.
+                self.backoff.reset();
                 log::debug!("Philosopher {} is eating", self.id);
                 self.state = PhilosopherState::Eating;
-                self.sleep(self.random);
-                self.send_state();
+                self.emit(EventKind::StartedEating);
+                self.sleep(Activity::Eating);
+                self.emit(EventKind::FinishedEating);
                 log::debug!("Philosopher {} is full", self.id);
-            } else if self.has_starved_to_death() {
-                // Philosopher is hungry but could not pick up both forks, so
-                // we check if philosopher has starved to death
-                self.state = PhilosopherState::Dead;
-                self.send_state();
+            } else {
+                // Put down whichever fork was picked up before trying again.
+                let (left, right) = pickup_forks;
+                left.into_iter().chain(right).for_each(ForkGuard::put_back);
+                if self.has_starved_to_death() {
+                    // Philosopher is hungry but could not pick up both forks,
+                    // so we check if philosopher has starved to death
+                    self.state = PhilosopherState::Dead;
+                    self.emit(EventKind::Died);
+                } else {
+                    self.backoff.wait(&self.clock);
+                }
             }
         }
     }
 
+    fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    fn rng(&self) -> RefMut<'_, StdRng> {
+        self.rng.borrow_mut()
+    }
+
     fn current_state(&self) -> PhilosopherState {
         self.state
     }
@@ -0,0 +1,261 @@
+//! Drawing the table live in the terminal with ANSI escape codes.
+//!
+//! The philosophers sit round a circle with the forks between them. A fork
+//! that's been picked up moves next to whoever picked it up. Below the
+//! drawing there's a line per philosopher with their state, meals and forks.
+//! Each redraw moves the cursor back up over the last one, so anything
+//! printed before the drawing stays on screen.
+
+use crate::{Event, EventKind};
+use std::f64::consts::PI;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How often the drawing is refreshed at most.
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+const GRID_WIDTH: usize = 60;
+const GRID_HEIGHT: usize = 21;
+
+const RESET: &str = "\x1b[0m";
+const CLEAR_LINE: &str = "\x1b[K";
+const HIDE_CURSOR: &str = "\x1b[?25l";
+const SHOW_CURSOR: &str = "\x1b[?25h";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Seat {
+    Thinking,
+    Hungry,
+    Eating,
+    Dead,
+}
+
+impl Seat {
+    fn name(self) -> &'static str {
+        match self {
+            Seat::Thinking => "thinking",
+            Seat::Hungry => "hungry",
+            Seat::Eating => "eating",
+            Seat::Dead => "dead",
+        }
+    }
+
+    fn colour(self) -> &'static str {
+        match self {
+            Seat::Thinking => "\x1b[34m",
+            Seat::Hungry => "\x1b[33m",
+            Seat::Eating => "\x1b[32m",
+            Seat::Dead => "\x1b[31m",
+        }
+    }
+}
+
+/// The state of the table, built up from events and drawn every so often.
+pub struct LiveTable {
+    seats: Vec<Seat>,
+    meals: Vec<u64>,
+    /// Who is holding each fork, if anyone.
+    forks: Vec<Option<usize>>,
+    /// Time of the latest event.
+    now: Duration,
+    last_drawn: Option<Instant>,
+    /// How many lines the last redraw took up, to move back over them.
+    lines_drawn: usize,
+}
+
+impl LiveTable {
+    pub fn new(n_philosophers: usize) -> Self {
+        Self {
+            seats: vec![Seat::Thinking; n_philosophers],
+            meals: vec![0; n_philosophers],
+            forks: vec![None; n_philosophers],
+            now: Duration::ZERO,
+            last_drawn: None,
+            lines_drawn: 0,
+        }
+    }
+
+    pub fn update(&mut self, event: &Event) {
+        let seat = event.id - 1;
+        self.now = self.now.max(event.at);
+        match event.kind {
+            EventKind::StartedThinking => self.seats[seat] = Seat::Thinking,
+            EventKind::BecameHungry => self.seats[seat] = Seat::Hungry,
+            EventKind::StartedEating => self.seats[seat] = Seat::Eating,
+            EventKind::FinishedEating => self.meals[seat] += 1,
+            EventKind::Died => self.seats[seat] = Seat::Dead,
+            EventKind::AcquiredFork(fork) => self.forks[fork] = Some(event.id),
+            EventKind::ReleasedFork(fork) => self.forks[fork] = None,
+        }
+    }
+
+    /// Hide the cursor and draw the empty table.
+    pub fn start<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        write!(out, "{HIDE_CURSOR}")?;
+        self.draw(out)
+    }
+
+    /// Redraw, unless the last redraw was less than [`REFRESH_INTERVAL`] ago.
+    pub fn draw_if_due<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        match self.last_drawn {
+            Some(at) if at.elapsed() < REFRESH_INTERVAL => Ok(()),
+            _ => self.draw(out),
+        }
+    }
+
+    /// Draw the final state and give the cursor back, leaving the drawing on
+    /// screen.
+    pub fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        self.draw(out)?;
+        write!(out, "{SHOW_CURSOR}")?;
+        out.flush()
+    }
+
+    pub fn draw<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        self.last_drawn = Some(Instant::now());
+        let mut frame = String::new();
+        if self.lines_drawn > 0 {
+            frame.push_str(&format!("\x1b[{}A\r", self.lines_drawn));
+        }
+        frame.push_str(&format!(
+            "Elapsed: {:.2}s{CLEAR_LINE}\n",
+            self.now.as_secs_f64()
+        ));
+
+        for row in self.grid() {
+            let mut current = None;
+            for (c, colour) in row {
+                if colour != current {
+                    frame.push_str(colour.unwrap_or(RESET));
+                    current = colour;
+                }
+                frame.push(c);
+            }
+            frame.push_str(RESET);
+            frame.push_str(CLEAR_LINE);
+            frame.push('\n');
+        }
+
+        for (seat, state) in self.seats.iter().enumerate() {
+            let id = seat + 1;
+            let forks: Vec<String> = self
+                .forks
+                .iter()
+                .enumerate()
+                .filter(|(_, holder)| **holder == Some(id))
+                .map(|(fork, _)| fork.to_string())
+                .collect();
+            frame.push_str(&format!(
+                "P{id:<3}{}{:<10}{RESET}{:>6} meals   forks: {}{CLEAR_LINE}\n",
+                state.colour(),
+                state.name(),
+                self.meals[seat],
+                if forks.is_empty() {
+                    "-".to_string()
+                } else {
+                    forks.join(" ")
+                }
+            ));
+        }
+        self.lines_drawn = 1 + GRID_HEIGHT + self.seats.len();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+
+    /// The round table as a grid of characters, each with an optional
+    /// colour.
+    fn grid(&self) -> Vec<Vec<(char, Option<&'static str>)>> {
+        let mut grid = vec![vec![(' ', None); GRID_WIDTH]; GRID_HEIGHT];
+        let n = self.seats.len() as f64;
+        let centre_x = GRID_WIDTH as f64 / 2.0;
+        let centre_y = GRID_HEIGHT as f64 / 2.0;
+        // Characters are about twice as tall as they are wide.
+        let position = |slot: f64, radius: f64| {
+            let angle = -PI / 2.0 + 2.0 * PI * slot / n;
+            let x = centre_x + radius * 2.2 * angle.cos();
+            let y = centre_y + radius * angle.sin();
+            (x.round() as isize, y.round() as isize)
+        };
+
+        for (seat, state) in self.seats.iter().enumerate() {
+            let (x, y) = position(seat as f64, 9.0);
+            put(&mut grid, x, y, &format!("P{}", seat + 1), state.colour());
+        }
+        // Fork `f` lies between philosophers `f` and `f + 1`, i.e. half a
+        // seat before philosopher `f + 1`.
+        for (fork, holder) in self.forks.iter().enumerate() {
+            let between = fork as f64 - 0.5;
+            let ((x, y), colour) = match holder {
+                None => (position(between, 5.5), "\x1b[37m"),
+                Some(id) => {
+                    let towards = (*id - 1) as f64;
+                    // Going the short way round the table.
+                    let offset =
+                        (towards - between + n / 2.0).rem_euclid(n) - n / 2.0;
+                    (
+                        position(between + offset * 0.6, 7.0),
+                        self.seats[id - 1].colour(),
+                    )
+                }
+            };
+            put(&mut grid, x, y, &format!("f{fork}"), colour);
+        }
+        grid
+    }
+}
+
+/// Write `text` centred on `(x, y)`, dropping anything off the edge.
+fn put(
+    grid: &mut [Vec<(char, Option<&'static str>)>],
+    x: isize,
+    y: isize,
+    text: &str,
+    colour: &'static str,
+) {
+    let Some(row) = usize::try_from(y).ok().and_then(|y| grid.get_mut(y))
+    else {
+        return;
+    };
+    let start = x - text.chars().count() as isize / 2;
+    for (i, c) in text.chars().enumerate() {
+        if let Some(cell) = usize::try_from(start + i as isize)
+            .ok()
+            .and_then(|x| row.get_mut(x))
+        {
+            *cell = (c, Some(colour));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeline::tests::two_meals;
+
+    #[test]
+    fn the_last_frame_has_a_row_for_every_philosopher() {
+        let (_, events) = two_meals();
+        let mut table = LiveTable::new(2);
+        let mut out = Vec::new();
+        table.start(&mut out).unwrap();
+        for event in &events {
+            table.update(event);
+        }
+        table.finish(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        // Each frame after the first starts by moving back up over the last.
+        let frames: Vec<_> = out.split("A\r").collect();
+        assert_eq!(frames.len(), 2);
+        let rows: Vec<_> = frames[1]
+            .lines()
+            .filter(|line| line.starts_with('P'))
+            .collect();
+        assert_eq!(rows.len(), 2);
+        for (seat, row) in rows.iter().enumerate() {
+            assert!(row.starts_with(&format!("P{:<3}", seat + 1)), "{row}");
+            assert!(row.contains("thinking"), "{row}");
+            assert!(row.contains("1 meals   forks: -"), "{row}");
+        }
+    }
+}